@@ -0,0 +1,38 @@
+use std::io::{self, Write};
+
+use sha2::{Digest, Sha256};
+
+use crate::manifest::hex;
+
+/// Writer computing the SHA-256 digest of all the bytes written through it.
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Returns the hex-encoded digest of the bytes written so far.
+    pub fn hex_digest(&self) -> String {
+        hex(&self.hasher.clone().finalize())
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
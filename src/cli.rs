@@ -1,5 +1,18 @@
-use clap::Parser;
-use std::path::PathBuf;
+use chrono::Utc;
+use clap::{Parser, Subcommand, ValueEnum};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::output::OutputOptions;
+use transactions_processor::payment_engine::{
+    Amount, ClientStoreKind, DisputesOnLocked, OnUndisputed, PaymentEngineConfig,
+    TransactionStoreKind, TransactionType,
+};
 
 fn short_version() -> &'static str {
     let short_version = crate::VERSION.to_string();
@@ -27,13 +40,887 @@ fn long_version() -> &'static str {
 #[command(
     help_expected = true,
     disable_help_subcommand = true,
-    infer_subcommands = true
+    infer_subcommands = true,
+    subcommand_negates_reqs = true
 )]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     #[clap(
         value_name = "TRANSACTIONS_FILE",
         index = 1,
+        required = true,
         help = "Path to CSV file containing the transactions to process"
     )]
-    pub transactions_file: PathBuf,
+    pub transactions_file: Option<PathBuf>,
+
+    #[clap(
+        value_name = "MORE_TRANSACTIONS_FILES",
+        index = 2,
+        requires = "parallel_files",
+        help = "Additional CSV files with transactions of disjoint sets of clients"
+    )]
+    pub more_transactions_files: Vec<PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with = "stream_updates",
+        help = "Process each of the transaction files in a separate thread and merge the resulting accounts"
+    )]
+    pub parallel_files: bool,
+
+    #[clap(
+        long,
+        conflicts_with = "parallel_files",
+        help = "Read the transactions from the CSV files in a zip archive (implied by the `.zip` extension)"
+    )]
+    pub zip: bool,
+
+    #[clap(
+        long,
+        value_name = "DECIMAL",
+        default_value = "0",
+        help = "Maximum amount by which a withdrawal may exceed the available funds"
+    )]
+    pub withdrawal_epsilon: Decimal,
+
+    #[clap(
+        long,
+        help = "Reject withdrawals from clients that have not made any successful deposit yet"
+    )]
+    pub deny_withdrawal_before_deposit: bool,
+
+    #[clap(
+        long,
+        value_name = "DECIMAL",
+        help = "Reject disputes which would make the held funds of an account exceed this amount"
+    )]
+    pub max_held: Option<Decimal>,
+
+    #[clap(
+        long,
+        value_name = "DECIMAL",
+        help = "Resolve the oldest open disputes of an account whose held funds exceed this amount after a dispute"
+    )]
+    pub auto_resolve_over: Option<Decimal>,
+
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Forget undisputed transactions N transactions after they were processed, so that they can no longer be disputed"
+    )]
+    pub forget_settled_after: Option<NonZeroU64>,
+
+    #[clap(
+        long,
+        help = "Match disputes, resolves and chargebacks with a `ref` to the transaction of the same `ref` instead of the same `tx`"
+    )]
+    pub dispute_by_ref: bool,
+
+    #[clap(
+        long,
+        help = "Zero out held funds smaller than 0.0001 left after a chargeback"
+    )]
+    pub clamp_chargeback_residue: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = DisputesOnLocked::Allow,
+        help = "Whether dispute operations are accepted on locked accounts"
+    )]
+    pub disputes_on_locked: DisputesOnLocked,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = ClientStoreKind::Hashmap,
+        help = "Storage of the client accounts"
+    )]
+    pub client_store: ClientStoreKind,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = TransactionStoreKind::Memory,
+        help = "Storage of the processed transactions referenced by disputes and settles"
+    )]
+    pub transaction_index: TransactionStoreKind,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = OnUndisputed::Warn,
+        help = "Treatment of resolves/chargebacks of transactions which are not disputed"
+    )]
+    pub on_undisputed: OnUndisputed,
+
+    #[clap(
+        long,
+        help = "Silently ignore operations on locked accounts instead of rejecting them"
+    )]
+    pub treat_locked_as_readonly: bool,
+
+    #[clap(
+        long,
+        help = "Reject deposits and withdrawals to accounts which were not opened by an `open` transaction"
+    )]
+    pub require_explicit_open: bool,
+
+    #[clap(
+        long,
+        help = "Reject withdrawals and chargebacks which would make the total funds of an account negative"
+    )]
+    pub no_negative_total: bool,
+
+    #[clap(
+        long,
+        help = "Reverse the funds of chargebacks without locking the account"
+    )]
+    pub no_lock_on_chargeback: bool,
+
+    #[clap(
+        long,
+        help = "Reject disputes of transactions with a zero amount instead of holding no funds"
+    )]
+    pub reject_zero_amount_disputes: bool,
+
+    #[clap(
+        long,
+        help = "Ignore disputes of already disputed transactions instead of rejecting them"
+    )]
+    pub ignore_redundant_disputes: bool,
+
+    #[clap(
+        long,
+        help = "Reject disputes which would make the held funds of an account exceed its total funds"
+    )]
+    pub enforce_held_le_total: bool,
+
+    #[clap(
+        long,
+        help = "Reject resolves which would make the held funds of an account negative"
+    )]
+    pub guard_releases: bool,
+
+    #[clap(
+        long,
+        help = "Ignore identical retries of deposits/withdrawals and reject conflicting reuses of their ids"
+    )]
+    pub idempotent_retries: bool,
+
+    #[clap(
+        long,
+        help = "Output `held` as `held_abs` and `held_negative` columns instead of a signed value"
+    )]
+    pub split_sign: bool,
+
+    #[clap(
+        long,
+        conflicts_with = "split_sign",
+        help = "Output the magnitude of `held`, so that `available + held` may differ from `total`"
+    )]
+    pub held_magnitude: bool,
+
+    #[clap(
+        long,
+        help = "Output the highest total each account reached as a `peak_total` column"
+    )]
+    pub with_peak: bool,
+
+    #[clap(
+        long,
+        help = "Output the gross deposited and withdrawn amounts as `gross_deposits` and `gross_withdrawals` columns"
+    )]
+    pub with_flows: bool,
+
+    #[clap(
+        long,
+        help = "Add a `net_movement` column with the gross deposits minus the gross withdrawals"
+    )]
+    pub with_net_movement: bool,
+
+    #[clap(
+        long,
+        help = "Add an `available_net_of_pending` column with the available funds minus the pending fees"
+    )]
+    pub with_net_available: bool,
+
+    #[clap(
+        long,
+        help = "Track the clients with any failed transaction and mark them in a `had_errors` column"
+    )]
+    pub atomic_per_client: bool,
+
+    #[clap(
+        long,
+        requires = "atomic_per_client",
+        help = "Omit the accounts of the clients with any failed transaction from the output instead of marking them"
+    )]
+    pub exclude_failed_clients: bool,
+
+    #[clap(
+        long,
+        help = "Append a `TOTAL` row with the sums of the balances of all the accounts"
+    )]
+    pub summary_row: bool,
+
+    #[clap(
+        long,
+        value_name = "N",
+        help = "Pad the client ids in the accounts output with leading zeros to N digits"
+    )]
+    pub client_width: Option<usize>,
+
+    #[clap(
+        long,
+        help = "Replace the client ids in the output with a contiguous sequence starting at 1"
+    )]
+    pub normalize_clients: bool,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        requires = "normalize_clients",
+        help = "Write the mapping of the original client ids to the normalized ones to PATH"
+    )]
+    pub client_map: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        value_parser = read_client_aliases,
+        conflicts_with = "normalize_clients",
+        help = "CSV with `client,external_account` columns whose external accounts replace the client ids in the output"
+    )]
+    pub client_alias_file: Option<HashMap<u16, String>>,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = AliasMissing::Error,
+        requires = "client_alias_file",
+        help = "Treatment of the clients without an alias in `--client-alias-file`"
+    )]
+    pub alias_missing: AliasMissing,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        require_equals = true,
+        help = "Report disputes referencing transactions which never appeared to PATH (or stderr)"
+    )]
+    pub report_orphan_disputes: Option<Option<PathBuf>>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Report the dispute operations violating the dispute lifecycle to PATH"
+    )]
+    pub validate_lifecycle: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Write a JSON manifest describing the input and the run to PATH"
+    )]
+    pub manifest: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Write the SHA-256 checksum of the output to PATH in `sha256sum` format"
+    )]
+    pub checksum_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "Report the number of stored accounts and transactions with their estimated memory footprint to stderr"
+    )]
+    pub report_memory: bool,
+
+    #[clap(
+        long,
+        help = "Print the percentiles of the transaction processing latency to stderr"
+    )]
+    pub latency_stats: bool,
+
+    #[clap(
+        long,
+        help = "Do not log a warning for every transaction which failed to be processed"
+    )]
+    pub quiet: bool,
+
+    #[clap(
+        long,
+        value_name = "SECONDS",
+        value_parser = parse_duration,
+        help = "Stop processing and output the partial state once the run takes longer than SECONDS"
+    )]
+    pub max_duration: Option<Duration>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        value_parser = read_client_list,
+        help = "Process only the transactions of the clients listed in FILE, one id per line"
+    )]
+    pub allow_clients: Option<HashSet<u16>>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        value_parser = read_client_list,
+        help = "Skip the transactions of the clients listed in FILE, one id per line"
+    )]
+    pub deny_clients: Option<HashSet<u16>>,
+
+    #[clap(
+        long,
+        value_name = "LIST",
+        value_parser = parse_type_list,
+        help = "Reject the transactions of the types in the comma-separated LIST, e.g. `dispute,resolve,chargeback`"
+    )]
+    pub disable_types: Option<HashSet<&'static str>>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        value_parser = read_amount_overrides,
+        help = "CSV with `tx,amount` columns whose amounts replace the amounts of the given deposits and withdrawals"
+    )]
+    pub amount_overrides: Option<HashMap<u32, Decimal>>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Write the complete engine state, including the stored transactions, as JSON to PATH"
+    )]
+    pub dump_state: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "SCALE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "4",
+        help = "Report the accounts whose balances have more than SCALE decimal places [default: 4]"
+    )]
+    pub balance_precision_check: Option<u32>,
+
+    #[clap(
+        long,
+        help = "After processing, warn about the accounts which are not locked, but still hold funds"
+    )]
+    pub warn_held: bool,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Write the accounts locked during the run together with the locking transaction to PATH"
+    )]
+    pub locks_report: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "DIR",
+        help = "Write a statement with the operations and resulting balances of every account to DIR"
+    )]
+    pub journal_dir: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Write the transactions which are still disputed at the end of the run to PATH"
+    )]
+    pub open_disputes_report: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "DIR",
+        conflicts_with = "parallel_files",
+        help = "Write the accounts CSV to a new timestamped file in DIR every `--snapshot-interval` processed transactions"
+    )]
+    pub snapshot_dir: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "N",
+        default_value = "10000",
+        requires = "snapshot_dir",
+        help = "Number of processed transactions between two snapshots"
+    )]
+    pub snapshot_interval: NonZeroU64,
+
+    #[clap(
+        long,
+        value_name = "N",
+        default_value = "5",
+        requires = "snapshot_dir",
+        help = "Number of the most recent snapshots kept in the snapshot directory"
+    )]
+    pub snapshot_keep: NonZeroUsize,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::Csv,
+        requires_ifs([("parquet", "output"), ("msgpack", "output")]),
+        help = "Format of the accounts output"
+    )]
+    pub format: OutputFormat,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["stream_updates", "since_tx", "until_tx", "baseline", "checksum_file"],
+        help = "File to write the accounts to instead of stdout (required with `--format parquet` or `--format msgpack`), or the prefix of the files with `--max-rows-per-file`"
+    )]
+    pub output: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "N",
+        requires = "output",
+        conflicts_with = "format",
+        help = "Split the accounts CSV into `<PREFIX>.part1.csv`, `<PREFIX>.part2.csv`, ... of at most N rows each, with `--output <PREFIX>`"
+    )]
+    pub max_rows_per_file: Option<NonZeroU64>,
+
+    #[clap(
+        long,
+        value_name = "LIST",
+        value_parser = parse_scale_list,
+        conflicts_with_all = ["format", "output", "stream_updates", "since_tx", "until_tx"],
+        help = "Output the balances rounded to each of the comma-separated scales (e.g. `2,4`) and the difference of the totals at the smallest and the largest one"
+    )]
+    pub compare_scales: Option<BTreeSet<u32>>,
+
+    #[clap(
+        long,
+        help = "Output the state of the affected account as JSON after every processed transaction"
+    )]
+    pub stream_updates: bool,
+
+    #[clap(
+        long,
+        value_name = "CHAR",
+        help = "Skip input lines starting with this character"
+    )]
+    pub comment_char: Option<char>,
+
+    #[clap(long, help = "Accept input rows with missing trailing or extra fields")]
+    pub flexible: bool,
+
+    #[clap(
+        long,
+        value_name = "ID",
+        help = "Stop processing at the first transaction with id greater than ID (assumes monotonic ids)"
+    )]
+    pub as_of_tx: Option<u32>,
+
+    #[clap(
+        long,
+        help = "Abort if the ids of deposits and withdrawals are not strictly increasing"
+    )]
+    pub require_monotonic_tx: bool,
+
+    #[clap(
+        long,
+        value_name = "ID",
+        conflicts_with_all = ["as_of_tx", "parallel_files", "stream_updates"],
+        help = "Output the balance changes caused by the transactions with id greater or equal to ID"
+    )]
+    pub since_tx: Option<u32>,
+
+    #[clap(
+        long,
+        value_name = "ID",
+        conflicts_with_all = ["as_of_tx", "parallel_files", "stream_updates"],
+        help = "Output the balance changes caused by the transactions with id lower or equal to ID"
+    )]
+    pub until_tx: Option<u32>,
+
+    #[clap(
+        long,
+        value_name = "DECIMAL",
+        help = "Amount used for deposits and withdrawals with a missing amount"
+    )]
+    pub default_amount: Option<Decimal>,
+
+    #[clap(
+        long,
+        help = "Use the absolute value of the amounts, so that e.g. a withdrawal of -50 withdraws 50"
+    )]
+    pub abs_amounts: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = DecimalSeparator::Dot,
+        help = "Decimal separator of the amounts"
+    )]
+    pub decimal_separator: DecimalSeparator,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = InputFormat::Csv,
+        help = "Format of the transactions input"
+    )]
+    pub input_format: InputFormat,
+
+    #[clap(
+        long,
+        help = "Accept amounts in scientific notation, e.g. `1.5e2` for `150`"
+    )]
+    pub allow_scientific_notation: bool,
+
+    #[clap(
+        long,
+        value_name = "PERCENT",
+        help = "Fail the run if the percentage of failed transactions exceeds PERCENT"
+    )]
+    pub max_error_rate: Option<Decimal>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Accounts CSV from a previous run to compare the output against"
+    )]
+    pub baseline: Option<PathBuf>,
+
+    #[clap(
+        long,
+        requires = "baseline",
+        help = "Output only the accounts which differ from the baseline"
+    )]
+    pub diff_only: bool,
+
+    #[clap(
+        long,
+        value_name = "BYTES",
+        default_value_t = 64 * 1024,
+        help = "Size of the input and output buffers"
+    )]
+    pub buffer_size: usize,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Fail the run if the accounts do not match the accounts CSV at PATH"
+    )]
+    pub expect_accounts: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        conflicts_with = "parallel_files",
+        help = "Start from the accounts in the accounts CSV at PATH, e.g. the output of a previous run, instead of empty ones"
+    )]
+    pub seed_accounts: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        value_parser = read_opening_balances,
+        conflicts_with = "parallel_files",
+        help = "CSV with `client,opening_balance` columns whose balances are credited to the accounts before processing"
+    )]
+    pub opening_balances: Option<HashMap<u16, Decimal>>,
+
+    #[clap(
+        long,
+        value_name = "PATH",
+        help = "Write the counters of the run (rows, processed and failed transactions by error, clients, locked accounts, elapsed time) as JSON to PATH"
+    )]
+    pub report_json: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Process a built-in transaction sequence and verify the resulting accounts
+    Selftest,
+    /// Read transactions without a header from stdin line by line and print the state of the
+    /// affected account after each one
+    Repl,
+    /// Check the transactions CSV for problems without processing it
+    Validate {
+        #[clap(
+            value_name = "TRANSACTIONS_FILE",
+            help = "Path to CSV file containing the transactions to check"
+        )]
+        transactions_file: PathBuf,
+
+        #[clap(
+            long,
+            value_name = "N",
+            default_value = "10",
+            help = "Stop after reporting N problems (at least 1)"
+        )]
+        max_problems: NonZeroUsize,
+    },
+    /// Print the transactions undoing the deposits and withdrawals of the transactions CSV
+    Invert {
+        #[clap(
+            value_name = "TRANSACTIONS_FILE",
+            help = "Path to CSV file containing the transactions to undo"
+        )]
+        transactions_file: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// CSV written to stdout or `--output`.
+    #[default]
+    Csv,
+    /// Parquet file written to `--output`.
+    Parquet,
+    /// MessagePack file written to `--output`.
+    Msgpack,
+    /// Aligned text table written to stdout or `--output`, meant to be read by humans.
+    Table,
+    /// CSV with only the `client` and `locked` columns written to stdout or `--output`.
+    StatusOnly,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// CSV with a header row.
+    #[default]
+    Csv,
+    /// JSON Lines, i.e. a JSON object with the fields of the CSV columns on every line.
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DecimalSeparator {
+    /// Amounts are written as `1234.56`.
+    #[default]
+    Dot,
+    /// Amounts are written as `1.234,56`, with an optional dot as the thousands separator.
+    Comma,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum AliasMissing {
+    /// Writing the output fails.
+    #[default]
+    Error,
+    /// The client id is written as is.
+    Passthrough,
+}
+
+fn parse_duration(seconds: &str) -> Result<Duration, String> {
+    let seconds = seconds.parse::<f64>().map_err(|err| err.to_string())?;
+    Duration::try_from_secs_f64(seconds).map_err(|err| err.to_string())
+}
+
+/// Reads the client ids listed in the file at `path`, one per line. Empty lines are ignored.
+fn read_client_list(path: &str) -> Result<HashSet<u16>, String> {
+    let contents = fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            line.trim()
+                .parse()
+                .map_err(|err| format!("{path}:{}: {err}", index + 1))
+        })
+        .collect()
+}
+
+/// Parses a comma-separated list of transaction type names as used in the input, e.g.
+/// `dispute,resolve`.
+fn parse_type_list(list: &str) -> Result<HashSet<&'static str>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            TransactionType::NAMES
+                .into_iter()
+                .find(|known| *known == name)
+                .ok_or_else(|| {
+                    format!(
+                        "unknown transaction type {name} (expected one of {})",
+                        TransactionType::NAMES.join(", ")
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Parses a comma-separated list of at least two scales, e.g. `2,4`.
+fn parse_scale_list(list: &str) -> Result<BTreeSet<u32>, String> {
+    let scales = list
+        .split(',')
+        .map(str::trim)
+        .filter(|scale| !scale.is_empty())
+        .map(|scale| {
+            let scale = scale
+                .parse::<u32>()
+                .map_err(|err| format!("invalid scale {scale}: {err}"))?;
+            if scale > Amount::MAX_SCALE {
+                return Err(format!(
+                    "scale {scale} exceeds the scale of the amounts ({})",
+                    Amount::MAX_SCALE
+                ));
+            }
+            Ok(scale)
+        })
+        .collect::<Result<BTreeSet<_>, _>>()?;
+    if scales.len() < 2 {
+        return Err("at least two different scales are required".to_string());
+    }
+
+    Ok(scales)
+}
+
+#[derive(Deserialize)]
+struct AmountOverride {
+    tx: u32,
+    amount: Decimal,
+}
+
+#[derive(Deserialize)]
+struct ClientAlias {
+    client: u16,
+    external_account: String,
+}
+
+/// Reads the external accounts of the clients from the CSV at `path`. Every client may be listed
+/// at most once.
+fn read_client_aliases(path: &str) -> Result<HashMap<u16, String>, String> {
+    let mut reader = csv::Reader::from_path(path).map_err(|err| format!("{path}: {err}"))?;
+    let mut aliases = HashMap::new();
+    for row in reader.deserialize() {
+        let row: ClientAlias = row.map_err(|err| format!("{path}: {err}"))?;
+        if aliases.insert(row.client, row.external_account).is_some() {
+            return Err(format!(
+                "{path}: client {} has several external accounts",
+                row.client
+            ));
+        }
+    }
+
+    Ok(aliases)
+}
+
+#[derive(Deserialize)]
+struct OpeningBalance {
+    client: u16,
+    opening_balance: Decimal,
+}
+
+/// Reads the opening balances of the accounts from the CSV at `path`. Every client may be listed
+/// at most once.
+fn read_opening_balances(path: &str) -> Result<HashMap<u16, Decimal>, String> {
+    let mut reader = csv::Reader::from_path(path).map_err(|err| format!("{path}: {err}"))?;
+    let mut balances = HashMap::new();
+    for row in reader.deserialize() {
+        let row: OpeningBalance = row.map_err(|err| format!("{path}: {err}"))?;
+        if balances.insert(row.client, row.opening_balance).is_some() {
+            return Err(format!(
+                "{path}: client {} has several opening balances",
+                row.client
+            ));
+        }
+    }
+
+    Ok(balances)
+}
+
+/// Reads the corrected amounts of transactions from the CSV at `path`.
+fn read_amount_overrides(path: &str) -> Result<HashMap<u32, Decimal>, String> {
+    let mut reader = csv::Reader::from_path(path).map_err(|err| format!("{path}: {err}"))?;
+    reader
+        .deserialize()
+        .map(|row| {
+            row.map(|row: AmountOverride| (row.tx, row.amount))
+                .map_err(|err| format!("{path}: {err}"))
+        })
+        .collect()
+}
+
+impl Cli {
+    /// Whether the transactions of the `client` are to be processed according to
+    /// `--allow-clients` and `--deny-clients`. The denylist takes precedence.
+    pub fn is_client_allowed(&self, client: u16) -> bool {
+        !self
+            .deny_clients
+            .as_ref()
+            .is_some_and(|deny_clients| deny_clients.contains(&client))
+            && self
+                .allow_clients
+                .as_ref()
+                .is_none_or(|allow_clients| allow_clients.contains(&client))
+    }
+
+    /// The first transaction file, which clap requires unless a subcommand is run.
+    pub fn transactions_file(&self) -> anyhow::Result<&Path> {
+        self.transactions_file
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("the transactions file is required"))
+    }
+
+    /// All the transaction files given on the command line.
+    pub fn transactions_files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.transactions_file
+            .iter()
+            .chain(&self.more_transactions_files)
+    }
+}
+
+impl From<&Cli> for PaymentEngineConfig {
+    fn from(cli: &Cli) -> Self {
+        Self {
+            withdrawal_epsilon: cli.withdrawal_epsilon,
+            deny_withdrawal_before_deposit: cli.deny_withdrawal_before_deposit,
+            max_held: cli.max_held,
+            auto_resolve_over: cli.auto_resolve_over,
+            forget_settled_after: cli.forget_settled_after,
+            dispute_by_ref: cli.dispute_by_ref,
+            clamp_chargeback_residue: cli.clamp_chargeback_residue,
+            quiet: cli.quiet,
+            journal: cli.journal_dir.is_some(),
+            disputes_on_locked: cli.disputes_on_locked,
+            guard_releases: cli.guard_releases,
+            idempotent_retries: cli.idempotent_retries,
+            client_store: cli.client_store,
+            transaction_store: cli.transaction_index,
+            on_undisputed: cli.on_undisputed,
+            treat_locked_as_readonly: cli.treat_locked_as_readonly,
+            require_explicit_open: cli.require_explicit_open,
+            no_negative_total: cli.no_negative_total,
+            no_lock_on_chargeback: cli.no_lock_on_chargeback,
+            reject_zero_amount_disputes: cli.reject_zero_amount_disputes,
+            ignore_redundant_disputes: cli.ignore_redundant_disputes,
+            enforce_held_le_total: cli.enforce_held_le_total,
+            disabled_types: cli.disable_types.clone().unwrap_or_default(),
+            clock: Some(Utc::now),
+        }
+    }
+}
+
+impl From<&Cli> for OutputOptions {
+    fn from(cli: &Cli) -> Self {
+        Self {
+            split_sign: cli.split_sign,
+            held_magnitude: cli.held_magnitude,
+            with_peak: cli.with_peak,
+            with_flows: cli.with_flows,
+            with_net_movement: cli.with_net_movement,
+            with_net_available: cli.with_net_available,
+            summary_row: cli.summary_row,
+            client_width: cli.client_width,
+            client_aliases: cli.client_alias_file.clone(),
+            alias_missing: cli.alias_missing,
+            // known only after processing, see `write_output`
+            failed_clients: None,
+        }
+    }
 }
@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+use tracing::error;
+
+use transactions_processor::payment_engine::AccountStatus;
+
+/// How an account changed compared to a baseline.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountChange {
+    Added,
+    Changed,
+    Removed,
+    Unchanged,
+}
+
+/// Compares the `current` accounts against the `baseline` ones, returning all the accounts from
+/// both sets ordered by client. Removed accounts are represented by their baseline state.
+pub fn diff_accounts(
+    current: &[AccountStatus],
+    baseline: &[AccountStatus],
+) -> Vec<(AccountStatus, AccountChange)> {
+    let mut baseline: BTreeMap<(u16, Option<String>), &AccountStatus> = baseline
+        .iter()
+        .map(|account| ((account.client, account.currency.clone()), account))
+        .collect();
+
+    let mut changes: Vec<(AccountStatus, AccountChange)> = current
+        .iter()
+        .map(|account| {
+            let change = match baseline.remove(&(account.client, account.currency.clone())) {
+                None => AccountChange::Added,
+                Some(previous) if is_same_state(account, previous) => AccountChange::Unchanged,
+                Some(_) => AccountChange::Changed,
+            };
+            (account.clone(), change)
+        })
+        .collect();
+    changes.extend(
+        baseline
+            .into_values()
+            .map(|account| (account.clone(), AccountChange::Removed)),
+    );
+    changes.sort_by(|(a, _), (b, _)| (a.client, &a.currency).cmp(&(b.client, &b.currency)));
+
+    changes
+}
+
+/// Computes the change of the balances of every account between the `baseline` and the `current`
+/// state. The changes are represented as accounts whose balances hold the differences, ordered by
+/// client.
+pub fn account_deltas(current: &[AccountStatus], baseline: &[AccountStatus]) -> Vec<AccountStatus> {
+    let mut deltas: BTreeMap<(u16, Option<String>), AccountStatus> = BTreeMap::new();
+    for (account, sign) in current.iter().map(|account| (account, Decimal::ONE)).chain(
+        baseline
+            .iter()
+            .map(|account| (account, Decimal::NEGATIVE_ONE)),
+    ) {
+        let delta = deltas
+            .entry((account.client, account.currency.clone()))
+            .or_insert_with(|| AccountStatus {
+                currency: account.currency.clone(),
+                ..AccountStatus::new(account.client)
+            });
+        delta.available += sign * account.available;
+        delta.held += sign * account.held;
+        delta.total += sign * account.total;
+    }
+
+    deltas.into_values().collect()
+}
+
+/// Compares the `actual` accounts against the `expected` ones, logging every mismatch. Returns
+/// the number of mismatched accounts.
+pub fn check_expected_accounts(actual: &[AccountStatus], expected: &[AccountStatus]) -> usize {
+    let mut mismatches = 0;
+    for (account, change) in diff_accounts(actual, expected) {
+        let client = account.client;
+        match change {
+            AccountChange::Unchanged => continue,
+            AccountChange::Added => error!(client, "unexpected account"),
+            AccountChange::Removed => error!(client, "missing expected account"),
+            AccountChange::Changed => error!(
+                client,
+                available = %account.available,
+                held = %account.held,
+                total = %account.total,
+                locked = account.locked,
+                "account does not match the expected state"
+            ),
+        }
+        mismatches += 1;
+    }
+
+    mismatches
+}
+
+/// Number of decimal places the balances are rounded to before being compared.
+const COMPARISON_SCALE: u32 = 4;
+
+fn is_same_state(a: &AccountStatus, b: &AccountStatus) -> bool {
+    a.available.round_dp(COMPARISON_SCALE) == b.available.round_dp(COMPARISON_SCALE)
+        && a.held.round_dp(COMPARISON_SCALE) == b.held.round_dp(COMPARISON_SCALE)
+        && a.total.round_dp(COMPARISON_SCALE) == b.total.round_dp(COMPARISON_SCALE)
+        && a.locked == b.locked
+}
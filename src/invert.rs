@@ -0,0 +1,261 @@
+use std::io::{Read, Write};
+
+use anyhow::{Context, Result};
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+
+use transactions_processor::payment_engine::{
+    CsvTransaction, DisputeEvent, PaymentEngine, Transaction, TransactionType,
+};
+
+/// Outcome of [`invert_transactions`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct InvertReport {
+    /// Number of the written inverse transactions.
+    pub inverted: usize,
+    /// Number of the successfully processed transactions which could not be inverted, including
+    /// the ones whose inverse transactions would be rejected as their account is locked.
+    pub skipped: usize,
+}
+
+/// Writes the transactions undoing the transactions CSV from the `input` to the `output`.
+///
+/// The input is processed by an engine with the default configuration first, so that only the
+/// transactions which succeeded are undone. Deposits become withdrawals and vice versa, written in
+/// the reverse order with new ids following the highest id of the input. Settled pending deposits
+/// are inverted like deposits, while the unsettled ones are skipped, as their funds are not
+/// available yet. Deposits and withdrawals which were charged back are not inverted as their funds
+/// were already reversed, neither are the ones still under dispute, whose funds are held, nor the
+/// ones of locked accounts, which reject any deposits and withdrawals. All the other transactions
+/// are skipped, as e.g. a chargeback cannot unlock an account.
+pub fn invert_transactions<R: Read, W: Write>(input: R, output: W) -> Result<InvertReport> {
+    let mut reader = ReaderBuilder::new().from_reader(input);
+    let headers = reader.headers()?.clone();
+    CsvTransaction::validate_headers(&headers)?;
+
+    let mut engine = PaymentEngine::new();
+    let mut report = InvertReport::default();
+    let mut to_invert = Vec::new();
+    let mut max_transaction_id = 0;
+    let mut record = StringRecord::new();
+    while reader.read_record(&mut record)? {
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+        // the rows which cannot be parsed were rejected by the original run as well
+        let Ok(transaction) =
+            CsvTransaction::from_record(&record, &headers).and_then(Transaction::try_from)
+        else {
+            continue;
+        };
+        max_transaction_id = max_transaction_id.max(transaction.id);
+        if engine.process_transaction(transaction.clone()).is_err() {
+            continue;
+        }
+
+        match transaction.r#type {
+            TransactionType::Deposit { .. }
+            | TransactionType::Withdrawal { .. }
+            | TransactionType::PendingDeposit { .. } => to_invert.push(transaction),
+            // undone together with the transactions they refer to
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Settle => {}
+            _ => report.skipped += 1,
+        }
+    }
+
+    let mut writer = WriterBuilder::new().from_writer(output);
+    writer.write_record(["type", "client", "tx", "amount", "currency"])?;
+    let mut next_transaction_id = max_transaction_id;
+    for transaction in to_invert.iter().rev() {
+        let lifecycle = engine
+            .transaction_lifecycle(transaction.id)
+            .expect("processed deposits and withdrawals are stored");
+        let is_pending = engine
+            .get_transaction(transaction.id)
+            .is_some_and(|transaction| transaction.is_pending());
+        let is_locked = engine
+            .get_account(transaction.client, transaction.currency.as_deref())
+            .is_some_and(|account| account.locked);
+        // still disputed, already reversed by a chargeback, not settled, or the inverse
+        // transaction would be rejected
+        if lifecycle.last() == Some(&DisputeEvent::Disputed)
+            || lifecycle.contains(&DisputeEvent::ChargedBack)
+            || is_pending
+            || is_locked
+        {
+            report.skipped += 1;
+            continue;
+        }
+
+        let (inverse_type, amount) = match transaction.r#type {
+            TransactionType::Deposit { amount } | TransactionType::PendingDeposit { amount } => {
+                ("withdrawal", amount)
+            }
+            TransactionType::Withdrawal { amount } => ("deposit", amount),
+            _ => unreachable!("only deposits, pending deposits and withdrawals are inverted"),
+        };
+        next_transaction_id = next_transaction_id
+            .checked_add(1)
+            .context("no transaction ids are left for the inverse transactions")?;
+        writer.write_record([
+            inverse_type,
+            &transaction.client.to_string(),
+            &next_transaction_id.to_string(),
+            &amount.value().to_string(),
+            transaction.currency.as_deref().unwrap_or_default(),
+        ])?;
+        report.inverted += 1;
+    }
+    writer.flush()?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use super::*;
+
+    fn invert(input: &str) -> (InvertReport, String) {
+        let mut output = Vec::new();
+        let report = invert_transactions(input.as_bytes(), &mut output).unwrap();
+        (report, String::from_utf8(output).unwrap())
+    }
+
+    fn process(engine: &mut PaymentEngine, input: &str) {
+        let mut reader = ReaderBuilder::new().from_reader(input.as_bytes());
+        let headers = reader.headers().unwrap().clone();
+        for record in reader.records() {
+            let transaction = CsvTransaction::from_record(&record.unwrap(), &headers).unwrap();
+            engine
+                .process_transaction(transaction.try_into().unwrap())
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_invert_transactions() {
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,100.0\n\
+                     withdrawal,1,2,60.0\n\
+                     deposit,2,3,5.5\n\
+                     withdrawal,2,4,10.0\n";
+
+        let (report, inverse) = invert(input);
+
+        assert_eq!(
+            report,
+            InvertReport {
+                inverted: 3,
+                skipped: 0
+            }
+        );
+        assert_eq!(
+            inverse,
+            "type,client,tx,amount,currency\n\
+             withdrawal,2,5,5.5,\n\
+             deposit,1,6,60.0,\n\
+             withdrawal,1,7,100.0,\n"
+        );
+
+        let mut engine = PaymentEngine::new();
+        // the failed withdrawal of client 2 is not inverted
+        let input = input.replace("withdrawal,2,4,10.0\n", "");
+        process(&mut engine, &input);
+        process(&mut engine, &inverse);
+        for client in [1, 2] {
+            let account = engine.get_account(client, None).unwrap();
+            assert_eq!(account.available, dec!(0));
+            assert_eq!(account.held, dec!(0));
+            assert_eq!(account.total, dec!(0));
+        }
+    }
+
+    #[test]
+    fn test_invert_skips_dispute_flows() {
+        let input = "type,client,tx,amount,currency\n\
+                     deposit,1,1,10.0,EUR\n\
+                     deposit,1,2,20.0,EUR\n\
+                     dispute,1,2,,EUR\n\
+                     resolve,1,2,,EUR\n\
+                     deposit,2,3,5.0,\n\
+                     dispute,2,3,,\n\
+                     chargeback,2,3,,\n\
+                     deposit,3,4,1.0,\n\
+                     dispute,3,4,,\n";
+
+        let (report, inverse) = invert(input);
+
+        assert_eq!(
+            report,
+            InvertReport {
+                inverted: 2,
+                skipped: 3
+            }
+        );
+        assert_eq!(
+            inverse,
+            "type,client,tx,amount,currency\n\
+             withdrawal,1,5,20.0,EUR\n\
+             withdrawal,1,6,10.0,EUR\n"
+        );
+    }
+
+    #[test]
+    fn test_invert_pending_deposits() {
+        let input = "type,client,tx,amount\n\
+                     pending,1,1,10.0\n\
+                     settle,1,1,\n\
+                     deposit,1,2,5.0\n\
+                     pending,1,3,7.0\n";
+
+        let (report, inverse) = invert(input);
+
+        // the unsettled pending deposit is skipped
+        assert_eq!(
+            report,
+            InvertReport {
+                inverted: 2,
+                skipped: 1
+            }
+        );
+        assert_eq!(
+            inverse,
+            "type,client,tx,amount,currency\n\
+             withdrawal,1,4,5.0,\n\
+             withdrawal,1,5,10.0,\n"
+        );
+
+        let mut engine = PaymentEngine::new();
+        process(&mut engine, &input.replace("pending,1,3,7.0\n", ""));
+        process(&mut engine, &inverse);
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.total, dec!(0));
+    }
+
+    #[test]
+    fn test_invert_skips_locked_accounts() {
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,10.0\n\
+                     deposit,1,2,5.0\n\
+                     dispute,1,2,\n\
+                     chargeback,1,2,\n\
+                     deposit,2,3,1.0\n";
+
+        let (report, inverse) = invert(input);
+
+        // the chargeback, the charged back deposit and the deposit of the locked account
+        assert_eq!(
+            report,
+            InvertReport {
+                inverted: 1,
+                skipped: 3
+            }
+        );
+        assert_eq!(
+            inverse,
+            "type,client,tx,amount,currency\n\
+             withdrawal,2,4,1.0,\n"
+        );
+    }
+}
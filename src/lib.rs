@@ -0,0 +1 @@
+pub mod payment_engine;
@@ -1,18 +1,60 @@
+mod checksum;
 mod cli;
-mod payment_engine;
+mod diff;
+mod invert;
+mod manifest;
+mod output;
+mod parquet_output;
+mod precision;
+mod repl;
+mod rolling_writer;
+mod selftest;
+mod snapshot;
+#[cfg(test)]
+mod test_utils;
+mod validate;
 
-use std::fs::File;
-use std::io;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Seek, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
+use chrono::Utc;
 use clap::Parser;
-use csv::{Reader, WriterBuilder};
+use csv::{ReaderBuilder, StringRecord};
+use hdrhistogram::Histogram;
 use rust_decimal::Decimal;
-use serde::Deserialize;
-use tracing::warn;
+use serde::Serialize;
+use tracing::{error, info, warn};
+use zip::ZipArchive;
 
-use crate::cli::Cli;
-use crate::payment_engine::{PaymentEngine, Transaction, TransactionType};
+use crate::checksum::HashingWriter;
+use crate::cli::{Cli, Command, DecimalSeparator, InputFormat, OutputFormat};
+use crate::diff::{AccountChange, account_deltas, check_expected_accounts, diff_accounts};
+use crate::invert::invert_transactions;
+use crate::manifest::Manifest;
+use crate::output::{
+    OutputOptions, normalize_clients, read_accounts, remap_clients, write_account_changes,
+    write_account_deltas, write_account_statuses, write_accounts, write_accounts_msgpack,
+    write_accounts_table, write_client_map, write_journal, write_lifecycle_violations, write_locks,
+    write_open_disputes, write_orphan_disputes, write_scale_comparison,
+};
+use crate::parquet_output::write_accounts_parquet;
+use crate::precision::check_balance_precision;
+use crate::repl::run_repl;
+use crate::rolling_writer::RollingWriter;
+use crate::selftest::run_selftest;
+use crate::snapshot::SnapshotWriter;
+use crate::validate::validate_transactions;
+use transactions_processor::payment_engine::{
+    AccountStatus, CsvTransaction, CsvTransactionError, CsvTransactionType, DisputeTargets,
+    OnUndisputed, PaymentEngine, PaymentEngineConfig, PaymentEngineError, Transaction,
+};
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const GIT_HASH: &str = match option_env!("GIT_HASH") {
@@ -21,7 +63,26 @@ pub const GIT_HASH: &str = match option_env!("GIT_HASH") {
 };
 const BUILD_TIMESTAMP: &str = env!("GIT_TIMESTAMP");
 
-fn main() -> Result<()> {
+/// Exit code used when all the input was read, but some transactions failed to be processed.
+const EXIT_PROCESSING_ERRORS: u8 = 2;
+/// Exit code used when the run was aborted due to an input parsing or IO error.
+const EXIT_FATAL_ERROR: u8 = 3;
+/// Exit code used when the ratio of failed transactions exceeded `--max-error-rate`.
+const EXIT_ERROR_RATE_EXCEEDED: u8 = 4;
+/// Exit code used when the accounts did not match the `--expect-accounts`.
+const EXIT_ACCOUNTS_MISMATCH: u8 = 5;
+/// The processing was stopped early due to `--max-duration`, the output is partial.
+const EXIT_TIMED_OUT: u8 = 6;
+/// Exit code of the `selftest` subcommand when the engine produced unexpected results.
+const EXIT_SELFTEST_FAILED: u8 = 7;
+
+/// Precision of the latencies recorded with `--latency-stats`.
+const LATENCY_SIGNIFICANT_DIGITS: u8 = 3;
+
+/// Number of records processed between the checks of `--max-duration`.
+const DEADLINE_CHECK_INTERVAL: usize = 1024;
+
+fn main() -> ExitCode {
     tracing_subscriber::fmt()
         .with_writer(io::stderr)
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
@@ -29,121 +90,2724 @@ fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
-    let mut payment_engine = PaymentEngine::new();
+    match &cli.command {
+        Some(Command::Selftest) => selftest(),
+        Some(Command::Repl) => repl(&cli),
+        Some(Command::Validate {
+            transactions_file,
+            max_problems,
+        }) => validate(transactions_file, max_problems.get()),
+        Some(Command::Invert { transactions_file }) => invert(transactions_file),
+        None => run(&cli, io::stdout()),
+    }
+}
+
+fn repl(cli: &Cli) -> ExitCode {
+    let result = seeded_engine(cli)
+        .and_then(|engine| run_repl(engine, io::stdin().lock(), io::stdout().lock()));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(EXIT_FATAL_ERROR)
+        }
+    }
+}
+
+fn validate(transactions_file: &Path, max_problems: usize) -> ExitCode {
+    let result = File::open(transactions_file)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| {
+            validate_transactions(BufReader::new(file), max_problems, io::stdout().lock())
+        });
+    match result {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(_) => ExitCode::from(EXIT_PROCESSING_ERRORS),
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(EXIT_FATAL_ERROR)
+        }
+    }
+}
+
+fn invert(transactions_file: &Path) -> ExitCode {
+    let result = File::open(transactions_file)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| invert_transactions(BufReader::new(file), io::stdout().lock()));
+    match result {
+        Ok(report) => {
+            if report.skipped > 0 {
+                warn!(
+                    skipped = report.skipped,
+                    "some transactions cannot be inverted and were skipped"
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(EXIT_FATAL_ERROR)
+        }
+    }
+}
 
-    let file = File::open(cli.transactions_file)?;
-    let mut reader = Reader::from_reader(file);
-    let records = reader.deserialize::<CsvTransaction>();
-    for transaction in records {
-        let transaction: Transaction = transaction?.try_into()?;
-        let transaction_id = transaction.id;
+fn selftest() -> ExitCode {
+    match run_selftest() {
+        Ok(()) => {
+            println!("PASS");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            println!("FAIL: {err:#}");
+            ExitCode::from(EXIT_SELFTEST_FAILED)
+        }
+    }
+}
 
-        if let Err(err) = payment_engine.process_transaction(transaction) {
-            warn!(transaction_id, ?err, "transaction processing failed");
+fn run<W: Write>(cli: &Cli, output: W) -> ExitCode {
+    match process_file(cli, output) {
+        Ok(report) if report.timed_out => {
+            error!(
+                processed = report.total_transactions,
+                "processing exceeded the maximum duration, the output is partial"
+            );
+            ExitCode::from(EXIT_TIMED_OUT)
+        }
+        Ok(report) if report.account_mismatches > 0 => {
+            error!(
+                mismatches = report.account_mismatches,
+                "accounts do not match the expected accounts"
+            );
+            ExitCode::from(EXIT_ACCOUNTS_MISMATCH)
+        }
+        Ok(report)
+            if cli
+                .max_error_rate
+                .is_some_and(|max_error_rate| report.error_rate() > max_error_rate) =>
+        {
+            error!(
+                failed = report.failed_transactions,
+                total = report.total_transactions,
+                "error rate {:.2}% exceeds the maximum of {}%",
+                report.error_rate(),
+                cli.max_error_rate.unwrap_or_default(),
+            );
+            ExitCode::from(EXIT_ERROR_RATE_EXCEEDED)
+        }
+        Ok(report) if report.failed_transactions > 0 => {
+            error!(
+                failed = report.failed_transactions,
+                total = report.total_transactions,
+                "some transactions failed to be processed"
+            );
+            ExitCode::from(EXIT_PROCESSING_ERRORS)
+        }
+        Ok(_) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(EXIT_FATAL_ERROR)
         }
     }
+}
 
-    let accounts = payment_engine.get_accounts_statuses();
+fn process_file<W: Write>(cli: &Cli, output: W) -> Result<ProcessingReport> {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let deadline = cli
+        .max_duration
+        .map(|max_duration| Instant::now() + max_duration);
 
-    let mut writer = WriterBuilder::new().from_writer(io::stdout());
-    for account in accounts {
-        writer.serialize(&account)?;
+    // the windowed output is the difference between the state as of the end of the window and
+    // the state just before it
+    let is_windowed = cli.since_tx.is_some() || cli.until_tx.is_some();
+    let windowed_cli;
+    let cli = if is_windowed {
+        windowed_cli = Cli {
+            as_of_tx: cli.until_tx,
+            ..cli.clone()
+        };
+        &windowed_cli
+    } else {
+        cli
+    };
+
+    let mut output = HashingWriter::new(BufWriter::with_capacity(cli.buffer_size, output));
+
+    let (mut report, payment_engine) = if cli.parallel_files {
+        process_files_in_parallel(cli, deadline)?
+    } else {
+        let mut payment_engine = seeded_engine(cli)?;
+        let transactions_file = cli.transactions_file()?;
+        let file = File::open(transactions_file)?;
+        let input = BufReader::with_capacity(cli.buffer_size, file);
+        let updates = cli.stream_updates.then_some(&mut output as &mut dyn Write);
+        let is_zip = cli.zip
+            || transactions_file
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+        let report = if is_zip {
+            process_zip_archive(cli, &mut payment_engine, input, updates, deadline)?
+        } else {
+            process_records(cli, &mut payment_engine, input, updates, deadline)?
+        };
+        (report, payment_engine)
+    };
+    if report.total_transactions == 0 {
+        info!("no transactions were processed");
+    }
+
+    if let Some(dump_file) = &cli.dump_state {
+        let mut dump_file = BufWriter::new(File::create(dump_file)?);
+        serde_json::to_writer_pretty(&mut dump_file, &payment_engine.state())?;
+        dump_file.flush()?;
+    }
+
+    report.write_latency_stats(io::stderr())?;
+
+    if let Some(overrides) = &cli.amount_overrides {
+        for &transaction_id in overrides.keys() {
+            if !report.applied_overrides.contains(&transaction_id) {
+                warn!(
+                    transaction_id,
+                    "amount override for a transaction which was not processed"
+                );
+            }
+        }
+    }
+
+    if cli.report_memory {
+        let usage = payment_engine.memory_usage();
+        eprintln!(
+            "clients: {}, transactions: {}, estimated memory: {} bytes",
+            usage.clients, usage.transactions, usage.estimated_bytes
+        );
+    }
+
+    let mut accounts = payment_engine.get_accounts_statuses();
+
+    if let Some(journal_dir) = &cli.journal_dir {
+        fs::create_dir_all(journal_dir)?;
+        for account in &accounts {
+            let file_name = match &account.currency {
+                Some(currency) => format!("{}-{currency}.csv", account.client),
+                None => format!("{}.csv", account.client),
+            };
+            write_journal(File::create(journal_dir.join(file_name))?, &account.journal)?;
+        }
+    }
+
+    if let Some(expected_accounts_file) = &cli.expect_accounts {
+        let expected_accounts = read_accounts(File::open(expected_accounts_file)?)?;
+        report.account_mismatches = check_expected_accounts(&accounts, &expected_accounts);
+    }
+
+    if let Some(scale) = cli.balance_precision_check {
+        check_balance_precision(&accounts, scale);
+    }
+
+    if cli.warn_held {
+        warn_held_funds(&accounts);
+    }
+
+    if cli.exclude_failed_clients {
+        accounts.retain(|account| !report.failed_clients.contains(&account.client));
+    }
+
+    // the accounts are paired with the baseline ones by their original client ids
+    let mut deltas = if is_windowed {
+        let baseline_accounts = match cli.since_tx {
+            Some(since_tx) if since_tx > 0 => {
+                let baseline_cli = Cli {
+                    as_of_tx: Some(since_tx - 1),
+                    snapshot_dir: None,
+                    ..cli.clone()
+                };
+                let mut baseline_engine = seeded_engine(&baseline_cli)?;
+                let file = File::open(cli.transactions_file()?)?;
+                let input = BufReader::with_capacity(cli.buffer_size, file);
+                process_records(&baseline_cli, &mut baseline_engine, input, None, None)?;
+                baseline_engine.get_accounts_statuses()
+            }
+            _ => Vec::new(),
+        };
+        Some(account_deltas(&accounts, &baseline_accounts))
+    } else {
+        None
+    };
+
+    if cli.normalize_clients {
+        let mapping = match &mut deltas {
+            // the deltas include the clients of both the accounts and the baseline
+            Some(deltas) => {
+                let mapping = normalize_clients(deltas);
+                remap_clients(&mut accounts, &mapping);
+                mapping
+            }
+            None => normalize_clients(&mut accounts),
+        };
+        if let Some(client_map_file) = &cli.client_map {
+            write_client_map(File::create(client_map_file)?, &mapping)?;
+        }
+    }
+
+    if let Some(deltas) = &deltas {
+        write_account_deltas(&mut output, deltas, &OutputOptions::from(cli))?;
+    } else if let Some(scales) = &cli.compare_scales {
+        write_scale_comparison(&mut output, &accounts, scales)?;
+    } else if let (Some(max_rows), Some(prefix)) = (cli.max_rows_per_file, &cli.output) {
+        let mut writer = RollingWriter::new(prefix, max_rows);
+        write_output(cli, &accounts, &report, &mut writer)?;
+        writer.finish()?;
+    } else if let (OutputFormat::Parquet, Some(output_file)) = (cli.format, &cli.output) {
+        write_accounts_parquet(File::create(output_file)?, &accounts)?;
+    } else if let (OutputFormat::Msgpack, Some(output_file)) = (cli.format, &cli.output) {
+        write_accounts_msgpack(BufWriter::new(File::create(output_file)?), &accounts)?;
+    } else if let Some(output_file) = &cli.output {
+        let mut output_file = BufWriter::new(File::create(output_file)?);
+        write_text_output(cli, &accounts, &report, &mut output_file, false)?;
+        output_file.flush()?;
+    } else if cli.format != OutputFormat::Csv || !cli.stream_updates {
+        write_text_output(
+            cli,
+            &accounts,
+            &report,
+            &mut output,
+            io::stdout().is_terminal(),
+        )?;
+    }
+    output.flush()?;
+    if let Some(checksum_file) = &cli.checksum_file {
+        // the output is written to stdout, which `sha256sum` denotes as `-`
+        fs::write(checksum_file, format!("{}  -\n", output.hex_digest()))?;
+    }
+
+    if let Some(orphan_disputes_file) = &cli.report_orphan_disputes {
+        match orphan_disputes_file {
+            Some(path) => write_orphan_disputes(File::create(path)?, &report.orphan_disputes)?,
+            None => write_orphan_disputes(io::stderr(), &report.orphan_disputes)?,
+        }
+    }
+
+    if let Some(lifecycle_file) = &cli.validate_lifecycle {
+        write_lifecycle_violations(File::create(lifecycle_file)?, &report.lifecycle_violations)?;
+    }
+
+    if let Some(locks_file) = &cli.locks_report {
+        write_locks(File::create(locks_file)?, &accounts)?;
+    }
+
+    if let Some(open_disputes_file) = &cli.open_disputes_report {
+        write_open_disputes(
+            File::create(open_disputes_file)?,
+            &payment_engine.open_disputes(),
+        )?;
+    }
+
+    if let Some(report_file) = &cli.report_json {
+        let mut report_file = BufWriter::new(File::create(report_file)?);
+        report.write_json(
+            &mut report_file,
+            &payment_engine.get_accounts_statuses(),
+            payment_engine.dispute_targets(),
+            started.elapsed(),
+        )?;
+        report_file.flush()?;
+    }
+
+    if let Some(manifest_file) = &cli.manifest {
+        let manifest = Manifest::new(
+            cli.transactions_file()?,
+            report.total_transactions,
+            started_at,
+            Utc::now(),
+        )?;
+        manifest.write(manifest_file)?;
+    }
+
+    Ok(report)
+}
+
+/// Creates an engine configured by the `cli` options with the accounts of `--seed-accounts` and
+/// the balances of `--opening-balances`, if given.
+fn seeded_engine(cli: &Cli) -> Result<PaymentEngine> {
+    let mut payment_engine = PaymentEngine::try_with_config(PaymentEngineConfig::from(cli))?;
+    if let Some(seed_accounts_file) = &cli.seed_accounts {
+        let accounts = read_accounts(File::open(seed_accounts_file)?)
+            .with_context(|| format!("failed to read {}", seed_accounts_file.display()))?;
+        for account in accounts {
+            let client = account.client;
+            payment_engine
+                .seed_account(account)
+                .with_context(|| format!("failed to seed the account of client {client}"))?;
+        }
+    }
+    for (&client, &balance) in cli.opening_balances.iter().flatten() {
+        payment_engine
+            .apply_opening_balance(client, balance)
+            .with_context(|| format!("failed to apply the opening balance of client {client}"))?;
+    }
+
+    Ok(payment_engine)
+}
+
+/// Writes the final `accounts` in the `--format`s which can be written to stdout, i.e. all but
+/// Parquet and MessagePack. The locked accounts of a table are highlighted if `is_terminal` is set.
+fn write_text_output<W: Write>(
+    cli: &Cli,
+    accounts: &[AccountStatus],
+    report: &ProcessingReport,
+    output: W,
+    is_terminal: bool,
+) -> Result<()> {
+    match cli.format {
+        OutputFormat::Table => write_accounts_table(output, accounts, is_terminal),
+        OutputFormat::StatusOnly => write_account_statuses(output, accounts),
+        OutputFormat::Csv | OutputFormat::Parquet | OutputFormat::Msgpack => {
+            write_output(cli, accounts, report, output)
+        }
+    }
+}
+
+/// Writes the final `accounts` in the format selected by the `cli` options.
+fn write_output<W: Write>(
+    cli: &Cli,
+    accounts: &[AccountStatus],
+    report: &ProcessingReport,
+    output: W,
+) -> Result<()> {
+    let mut output_options = OutputOptions::from(cli);
+    if cli.atomic_per_client && !cli.exclude_failed_clients {
+        output_options.failed_clients = Some(report.failed_clients.clone());
+    }
+    if let Some(baseline_file) = &cli.baseline {
+        let baseline = read_accounts(File::open(baseline_file)?)?;
+        let mut changes = diff_accounts(accounts, &baseline);
+        if cli.diff_only {
+            changes.retain(|(_, change)| *change != AccountChange::Unchanged);
+        }
+
+        write_account_changes(output, &changes, &output_options)?;
+    } else {
+        write_accounts(output, accounts, &output_options)?;
     }
 
     Ok(())
 }
 
-#[derive(Debug, Deserialize)]
-pub struct CsvTransaction {
-    #[serde(rename = "type")]
-    pub transaction_type: CsvTransactionType,
-    pub client: u16,
-    pub tx: u32,
-    pub amount: Option<Decimal>,
+/// Logs a warning for every account which is not locked, but holds funds of unresolved disputes
+/// or pending deposits.
+fn warn_held_funds(accounts: &[AccountStatus]) {
+    for account in accounts {
+        if !account.locked && !account.held.is_zero() {
+            warn!(
+                client = account.client,
+                held = %account.held,
+                "account still holds funds at the end of the run"
+            );
+        }
+    }
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
-#[serde(rename_all = "lowercase")]
-pub enum CsvTransactionType {
-    Deposit,
-    Withdrawal,
-    Dispute,
-    Resolve,
-    Chargeback,
+/// Outcome of processing all the input records, apart from the final accounts state.
+#[derive(Debug, Default)]
+pub struct ProcessingReport {
+    /// Number of records read from the input, including the skipped ones. Blank lines are not
+    /// records, the CSV reader drops them.
+    pub total_rows: usize,
+    /// Number of transactions read from the input.
+    pub total_transactions: usize,
+    /// Number of transactions which failed to be processed by the engine.
+    pub failed_transactions: usize,
+    /// Number of the failed transactions by the [`PaymentEngineError::code`] of their error.
+    pub failures_by_error: BTreeMap<&'static str, usize>,
+    /// Number of accounts which did not match the `--expect-accounts`.
+    pub account_mismatches: usize,
+    /// Dispute operations whose referenced transaction never appeared in the input.
+    pub orphan_disputes: Vec<Transaction>,
+    /// Dispute operations which were rejected as they do not follow the dispute lifecycle of the
+    /// referenced transaction, recorded with `--validate-lifecycle`.
+    pub lifecycle_violations: Vec<Transaction>,
+    /// Whether the processing was stopped early due to `--max-duration`.
+    pub timed_out: bool,
+    /// Processing times of the transactions in nanoseconds, recorded with `--latency-stats`.
+    pub latencies: Option<Histogram<u64>>,
+    /// Clients with any failed transaction, recorded with `--atomic-per-client`.
+    pub failed_clients: HashSet<u16>,
+    /// Transactions successfully processed with the amount of `--amount-overrides`.
+    pub applied_overrides: HashSet<u32>,
 }
 
-impl TryFrom<CsvTransaction> for Transaction {
-    type Error = anyhow::Error;
+impl ProcessingReport {
+    /// Adds the results of `other` to this report.
+    pub fn merge(&mut self, other: ProcessingReport) {
+        self.total_rows += other.total_rows;
+        self.total_transactions += other.total_transactions;
+        self.failed_transactions += other.failed_transactions;
+        for (code, count) in other.failures_by_error {
+            *self.failures_by_error.entry(code).or_default() += count;
+        }
+        self.account_mismatches += other.account_mismatches;
+        self.orphan_disputes.extend(other.orphan_disputes);
+        self.lifecycle_violations.extend(other.lifecycle_violations);
+        self.timed_out |= other.timed_out;
+        self.failed_clients.extend(other.failed_clients);
+        self.applied_overrides.extend(other.applied_overrides);
+        match (&mut self.latencies, other.latencies) {
+            (Some(latencies), Some(other_latencies)) => latencies
+                .add(other_latencies)
+                .expect("latency histograms are auto-resizing"),
+            (latencies @ None, other_latencies) => *latencies = other_latencies,
+            (Some(_), None) => {}
+        }
+    }
+
+    /// Counts a transaction which failed with the error of the given `code`.
+    fn record_failure(&mut self, code: &'static str) {
+        self.failed_transactions += 1;
+        *self.failures_by_error.entry(code).or_default() += 1;
+    }
 
-    fn try_from(value: CsvTransaction) -> Result<Self, Self::Error> {
-        let transaction_type = match value.transaction_type {
-            CsvTransactionType::Deposit => TransactionType::Deposit {
-                amount: value
-                    .amount
-                    .ok_or_else(|| anyhow!("amount is required for deposit"))?,
-            },
-            CsvTransactionType::Withdrawal => TransactionType::Withdrawal {
-                amount: value
-                    .amount
-                    .ok_or_else(|| anyhow!("amount is required for withdrawal"))?,
+    /// Writes the counters of the run with the number of distinct clients and locked `accounts`
+    /// and the kinds of the disputed transactions as a single JSON document, see `--report-json`.
+    pub fn write_json<W: Write>(
+        &self,
+        writer: W,
+        accounts: &[AccountStatus],
+        dispute_targets: DisputeTargets,
+        elapsed: Duration,
+    ) -> Result<()> {
+        #[derive(Serialize)]
+        struct JsonReport<'a> {
+            total_rows: usize,
+            processed: usize,
+            failed: usize,
+            failed_by_error: &'a BTreeMap<&'static str, usize>,
+            distinct_clients: usize,
+            locked_accounts: usize,
+            dispute_targets: DisputeTargets,
+            elapsed_ms: u128,
+        }
+
+        serde_json::to_writer_pretty(
+            writer,
+            &JsonReport {
+                total_rows: self.total_rows,
+                processed: self.total_transactions - self.failed_transactions,
+                failed: self.failed_transactions,
+                failed_by_error: &self.failures_by_error,
+                distinct_clients: accounts
+                    .iter()
+                    .map(|account| account.client)
+                    .collect::<HashSet<_>>()
+                    .len(),
+                locked_accounts: accounts.iter().filter(|account| account.locked).count(),
+                dispute_targets,
+                elapsed_ms: elapsed.as_millis(),
             },
-            CsvTransactionType::Dispute => TransactionType::Dispute,
-            CsvTransactionType::Resolve => TransactionType::Resolve,
-            CsvTransactionType::Chargeback => TransactionType::Chargeback,
+        )?;
+
+        Ok(())
+    }
+
+    /// Writes the percentiles of the transaction processing times recorded with
+    /// `--latency-stats`.
+    pub fn write_latency_stats<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let Some(latencies) = &self.latencies else {
+            return Ok(());
+        };
+
+        writeln!(
+            writer,
+            "transaction processing latency ({} transactions):",
+            latencies.len()
+        )?;
+        for percentile in [50.0, 95.0, 99.0] {
+            writeln!(
+                writer,
+                "  p{percentile}: {} ns",
+                latencies.value_at_percentile(percentile)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Percentage of the transactions which failed to be processed.
+    pub fn error_rate(&self) -> Decimal {
+        if self.total_transactions == 0 {
+            return Decimal::ZERO;
+        }
+
+        Decimal::from(self.failed_transactions) * Decimal::ONE_HUNDRED
+            / Decimal::from(self.total_transactions)
+    }
+}
+
+/// Processes the CSV files contained in the zip `archive` in the order of their names.
+fn process_zip_archive<R: Read + Seek>(
+    cli: &Cli,
+    payment_engine: &mut PaymentEngine,
+    archive: R,
+    mut updates: Option<&mut dyn Write>,
+    deadline: Option<Instant>,
+) -> Result<ProcessingReport> {
+    let mut archive = ZipArchive::new(archive)?;
+    let mut names = archive.file_names().map(str::to_string).collect::<Vec<_>>();
+    names.sort();
+
+    let mut report = ProcessingReport::default();
+    for name in names {
+        let entry = archive.by_name(&name)?;
+        if entry.is_dir() {
+            continue;
+        }
+        if !name.to_ascii_lowercase().ends_with(".csv") {
+            warn!(name, "skipping non-CSV archive entry");
+            continue;
+        }
+
+        let entry_updates = updates
+            .as_mut()
+            .map(|updates| &mut **updates as &mut dyn Write);
+        let entry_report = process_records(cli, payment_engine, entry, entry_updates, deadline)
+            .with_context(|| format!("failed to process archive entry {name}"))?;
+        report.merge(entry_report);
+        if report.timed_out {
+            break;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Processes each of the transaction files with its own engine in a separate thread. The files must
+/// contain disjoint sets of clients, so that the resulting accounts can be simply merged.
+fn process_files_in_parallel(
+    cli: &Cli,
+    deadline: Option<Instant>,
+) -> Result<(ProcessingReport, PaymentEngine)> {
+    let results = thread::scope(|scope| {
+        let handles = cli
+            .transactions_files()
+            .map(|path| {
+                scope.spawn(move || -> Result<_> {
+                    let mut payment_engine =
+                        PaymentEngine::try_with_config(PaymentEngineConfig::from(cli))?;
+                    let file = File::open(path)?;
+                    let input = BufReader::with_capacity(cli.buffer_size, file);
+                    let report = process_records(cli, &mut payment_engine, input, None, deadline)?;
+                    Ok((path, report, payment_engine))
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("processing thread panicked"))
+            .collect::<Vec<_>>()
+    });
+
+    let mut report = ProcessingReport::default();
+    let mut payment_engine = PaymentEngine::try_with_config(PaymentEngineConfig::from(cli))?;
+    for result in results {
+        let (path, file_report, file_engine) = result?;
+        payment_engine
+            .merge(file_engine)
+            .with_context(|| format!("{} overlaps with the other files", path.display()))?;
+        report.merge(file_report);
+    }
+
+    Ok((report, payment_engine))
+}
+
+/// Processes all the records from the `input` in the `--input-format`. If `updates` is given, the
+/// state of the affected account is written to it as a JSON line after every successfully
+/// processed transaction. The processing stops early once the `deadline` passes.
+fn process_records<R: Read>(
+    cli: &Cli,
+    payment_engine: &mut PaymentEngine,
+    input: R,
+    updates: Option<&mut dyn Write>,
+    deadline: Option<Instant>,
+) -> Result<ProcessingReport> {
+    match cli.input_format {
+        InputFormat::Csv => {
+            let comment = cli
+                .comment_char
+                .map(|comment_char| {
+                    u8::try_from(comment_char)
+                        .map_err(|_| anyhow!("comment character must be a single-byte character"))
+                })
+                .transpose()?;
+            let mut reader = ReaderBuilder::new()
+                .comment(comment)
+                .flexible(cli.flexible)
+                .from_reader(input);
+            let headers = reader.headers()?.clone();
+            CsvTransaction::validate_headers(&headers)?;
+            let amount_index = headers.iter().position(|header| header == "amount");
+            let transactions = reader.into_records().filter_map(|record| {
+                let mut record = match record {
+                    Ok(record) => record,
+                    Err(err) => return Some(Err(err.into())),
+                };
+                if cli.decimal_separator == DecimalSeparator::Comma
+                    && let Some(amount_index) = amount_index
+                {
+                    record = match localize_amount(&record, amount_index) {
+                        Ok(record) => record,
+                        Err(err) => return Some(Err(err)),
+                    };
+                }
+                if cli.allow_scientific_notation
+                    && let Some(amount_index) = amount_index
+                {
+                    record = expand_scientific_amount(&record, amount_index);
+                }
+                if record.iter().all(|field| field.trim().is_empty()) {
+                    return None;
+                }
+
+                Some(CsvTransaction::from_record(&record, &headers))
+            });
+            process_transactions(cli, payment_engine, transactions, updates, deadline)
+        }
+        InputFormat::Jsonl => {
+            let transactions = BufReader::new(input).lines().zip(1..).filter_map(
+                |(line, line_number)| match line {
+                    Ok(line) if line.trim().is_empty() => None,
+                    Ok(line) => Some(CsvTransaction::from_json_line(&line, line_number)),
+                    Err(err) => Some(Err(csv::Error::from(err).into())),
+                },
+            );
+            process_transactions(cli, payment_engine, transactions, updates, deadline)
+        }
+    }
+}
+
+/// Processes the parsed `transactions`, see [`process_records`].
+fn process_transactions(
+    cli: &Cli,
+    payment_engine: &mut PaymentEngine,
+    transactions: impl Iterator<Item = Result<CsvTransaction, CsvTransactionError>>,
+    mut updates: Option<&mut dyn Write>,
+    deadline: Option<Instant>,
+) -> Result<ProcessingReport> {
+    let mut report = ProcessingReport {
+        latencies: cli.latency_stats.then(|| {
+            Histogram::new(LATENCY_SIGNIFICANT_DIGITS).expect("the significant digits are valid")
+        }),
+        ..Default::default()
+    };
+    let mut snapshots = cli
+        .snapshot_dir
+        .as_ref()
+        .map(|snapshot_dir| {
+            SnapshotWriter::new(
+                snapshot_dir,
+                cli.snapshot_interval,
+                cli.snapshot_keep,
+                OutputOptions::from(cli),
+            )
+        })
+        .transpose()?;
+
+    let mut last_transaction_id = None;
+    for (index, csv_transaction) in transactions.enumerate() {
+        // checking the time for every record would be needlessly expensive
+        if let Some(deadline) = deadline
+            && index > 0
+            && index % DEADLINE_CHECK_INTERVAL == 0
+            && Instant::now() >= deadline
+        {
+            warn!(
+                processed = report.total_transactions,
+                "time budget exceeded, stopping early"
+            );
+            report.timed_out = true;
+            break;
+        }
+        report.total_rows += 1;
+
+        let csv_transaction = match csv_transaction {
+            Ok(csv_transaction) => {
+                let corrected_amount = cli
+                    .amount_overrides
+                    .as_ref()
+                    .and_then(|overrides| overrides.get(&csv_transaction.tx).copied());
+                csv_transaction
+                    .with_amount_override(corrected_amount)
+                    .with_default_amount(cli.default_amount)
+                    .with_abs_amount(cli.abs_amounts)
+            }
+            // there is no account the row could apply to, so it fails like a rejected transaction
+            Err(err @ CsvTransactionError::ClientOutOfRange { .. }) => {
+                if !cli.quiet {
+                    warn!(%err, "transaction processing failed");
+                }
+                report.total_transactions += 1;
+                report.record_failure("ClientOutOfRange");
+                continue;
+            }
+            Err(err) => return Err(err.into()),
         };
+        if !cli.is_client_allowed(csv_transaction.client) {
+            continue;
+        }
+        let transaction_id = csv_transaction.tx;
+        let client = csv_transaction.client;
+        // dispute operations reference the ids of the disputed transactions. Checked before the
+        // amount is validated, so that the rows failing due to their amount count as well.
+        if cli.require_monotonic_tx
+            && matches!(
+                csv_transaction.transaction_type,
+                CsvTransactionType::Deposit
+                    | CsvTransactionType::Withdrawal
+                    | CsvTransactionType::Pending
+            )
+        {
+            if let Some(last_transaction_id) = last_transaction_id
+                && transaction_id <= last_transaction_id
+            {
+                return Err(anyhow!(
+                    "transaction {transaction_id} is not greater than the previous transaction {last_transaction_id}"
+                ));
+            }
+            last_transaction_id = Some(transaction_id);
+        }
+        let transaction: Transaction = match csv_transaction.try_into() {
+            Ok(transaction) => transaction,
+            // an invalid amount fails only the transaction, like the other engine errors
+            Err(CsvTransactionError::Engine(err)) => {
+                if cli
+                    .as_of_tx
+                    .is_some_and(|as_of_tx| transaction_id > as_of_tx)
+                {
+                    break;
+                }
+
+                if !cli.quiet {
+                    warn!(transaction_id, ?err, "transaction processing failed");
+                }
+                report.total_transactions += 1;
+                report.record_failure(err.code());
+                if cli.atomic_per_client {
+                    report.failed_clients.insert(client);
+                }
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        // assumes monotonic transaction ids, so the rows following the first one past the cutoff,
+        // including the dispute operations referencing earlier transactions, come after it
+        if let Some(as_of_tx) = cli.as_of_tx
+            && transaction_id > as_of_tx
+        {
+            break;
+        }
+
+        report.total_transactions += 1;
+
+        let started_at = report.latencies.is_some().then(Instant::now);
+        let result = payment_engine.process_transaction(transaction.clone());
+        if let (Some(latencies), Some(started_at)) = (&mut report.latencies, started_at) {
+            latencies.saturating_record(started_at.elapsed().as_nanos() as u64);
+        }
+        match result {
+            Ok(()) => {
+                if transaction.r#type.amount().is_some()
+                    && cli
+                        .amount_overrides
+                        .as_ref()
+                        .is_some_and(|overrides| overrides.contains_key(&transaction_id))
+                {
+                    report.applied_overrides.insert(transaction_id);
+                }
+                if let Some(updates) = &mut updates {
+                    let account = payment_engine
+                        .get_account(transaction.client, transaction.currency.as_deref())
+                        .expect("BUG: account of a processed transaction must exist");
+                    serde_json::to_writer(&mut *updates, account)?;
+                    writeln!(updates)?;
+                }
+                if let Some(snapshots) = &mut snapshots {
+                    snapshots.transaction_processed(payment_engine)?;
+                }
+            }
+            Err(err @ PaymentEngineError::NotDisputed(_))
+                if cli.on_undisputed == OnUndisputed::Error =>
+            {
+                return Err(anyhow!(err).context(format!("transaction {transaction_id} failed")));
+            }
+            Err(err @ PaymentEngineError::TransactionStore(_)) => {
+                return Err(anyhow!(err).context(format!("transaction {transaction_id} failed")));
+            }
+            Err(err) => {
+                if !cli.quiet {
+                    warn!(transaction_id, ?err, "transaction processing failed");
+                }
+                report.record_failure(err.code());
+                if cli.atomic_per_client {
+                    report.failed_clients.insert(client);
+                }
+
+                if cli.validate_lifecycle.is_some()
+                    && matches!(
+                        err,
+                        PaymentEngineError::NotDisputed(_)
+                            | PaymentEngineError::TransactionAlreadyDisputed(_)
+                    )
+                {
+                    report.lifecycle_violations.push(transaction);
+                } else if cli.report_orphan_disputes.is_some()
+                    && matches!(
+                        err,
+                        PaymentEngineError::TransactionNotFound(_)
+                            | PaymentEngineError::UnknownClient(_)
+                    )
+                    // the transaction did appear, but was forgotten since
+                    && !payment_engine.is_forgotten(transaction.id)
+                {
+                    report.orphan_disputes.push(transaction);
+                }
+            }
+        }
+    }
+
+    // the referenced transaction might have appeared after the dispute
+    report
+        .orphan_disputes
+        .retain(|dispute| payment_engine.get_transaction(dispute.id).is_none());
+
+    Ok(report)
+}
+
+/// Rewrites the amount field of the `record` from the `1.234,56` notation to `1234.56`, so that it
+/// can be parsed as a [`CsvTransaction`]. Amounts which are not in the notation are rejected, see
+/// [`delocalize_amount`].
+fn localize_amount(
+    record: &StringRecord,
+    amount_index: usize,
+) -> Result<StringRecord, CsvTransactionError> {
+    let mut localized = StringRecord::new();
+    for (index, field) in record.iter().enumerate() {
+        if index == amount_index {
+            let amount = delocalize_amount(field).ok_or_else(|| {
+                CsvTransactionError::InvalidLocalizedAmount {
+                    line: record.position().map_or(0, |position| position.line()),
+                    amount: field.to_string(),
+                }
+            })?;
+            localized.push_field(&amount);
+        } else {
+            localized.push_field(field);
+        }
+    }
+    localized.set_position(record.position().cloned());
+
+    Ok(localized)
+}
 
-        Ok(Transaction::new(value.client, value.tx, transaction_type))
+/// Converts an amount in the `1.234,56` notation to `1234.56`. Dots are accepted only as the
+/// thousands separators of groups of three digits before a decimal comma, so e.g. `1.5` or `1.234`
+/// are rejected rather than read as `15` or `1234`.
+fn delocalize_amount(amount: &str) -> Option<String> {
+    let amount = amount.trim();
+    let (integer, fraction) = match amount.split_once(',') {
+        Some((_, fraction)) if fraction.contains(',') => return None,
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (amount, None),
+    };
+    if !integer.contains('.') {
+        return Some(match fraction {
+            Some(fraction) => format!("{integer}.{fraction}"),
+            None => amount.to_string(),
+        });
     }
+    let fraction = fraction?;
+
+    let digits = integer.trim_start_matches(['-', '+']);
+    let sign = &integer[..integer.len() - digits.len()];
+    let mut groups = digits.split('.');
+    let is_digits = |group: &str| group.bytes().all(|byte| byte.is_ascii_digit());
+    let first = groups.next()?;
+    if !(1..=3).contains(&first.len())
+        || !is_digits(first)
+        || !groups.all(|group| group.len() == 3 && is_digits(group))
+    {
+        return None;
+    }
+
+    Some(format!("{sign}{}.{fraction}", digits.replace('.', "")))
+}
+
+/// Rewrites the amount field of the `record` from the scientific notation, e.g. `1.5e2`, to
+/// `150`. Other amounts are left as they are.
+fn expand_scientific_amount(record: &StringRecord, amount_index: usize) -> StringRecord {
+    let mut expanded: StringRecord = record
+        .iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let amount = field.trim();
+            if index == amount_index
+                && amount.contains(['e', 'E'])
+                && let Ok(amount) = Decimal::from_scientific(amount)
+            {
+                Cow::Owned(amount.to_string())
+            } else {
+                Cow::Borrowed(field)
+            }
+        })
+        .collect();
+    expanded.set_position(record.position().cloned());
+
+    expanded
 }
 
 #[cfg(test)]
 mod tests {
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use std::{env, process};
+
+    use sha2::{Digest, Sha256};
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
     use super::*;
-    use csv::ReaderBuilder;
     use rust_decimal::dec;
+    use transactions_processor::payment_engine::{
+        MergeError, TransactionStoreKind, TransactionType,
+    };
+
+    fn parse_cli(args: &[&str]) -> Cli {
+        Cli::parse_from(
+            ["transactions-processor"]
+                .iter()
+                .chain(args)
+                .chain(&["transactions.csv"]),
+        )
+    }
+
+    /// Writes `contents` to a file in a fresh temporary directory and returns its path.
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = env::temp_dir().join(format!(
+            "transactions-processor-{}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_report_orphan_disputes() {
+        let cli = parse_cli(&["--report-orphan-disputes"]);
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    dispute,1,2,\n\
+                    dispute,1,3,\n\
+                    deposit,1,3,5.0\n\
+                    dispute,1,1,\n";
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.orphan_disputes.len(), 1);
+        assert_eq!(report.orphan_disputes[0].client, 1);
+        assert_eq!(report.orphan_disputes[0].id, 2);
+
+        let mut output = Vec::new();
+        write_orphan_disputes(&mut output, &report.orphan_disputes).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "type,client,tx\ndispute,1,2\n"
+        );
+    }
+
+    #[test]
+    fn test_report_orphan_disputes_forgotten() {
+        let cli = parse_cli(&["--report-orphan-disputes", "--forget-settled-after", "1"]);
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    deposit,1,2,5.0\n\
+                    dispute,1,1,\n\
+                    dispute,1,3,\n";
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig::from(&cli));
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        // the dispute of the forgotten deposit fails, but is no orphan
+        assert_eq!(report.failed_transactions, 2);
+        assert_eq!(report.orphan_disputes.len(), 1);
+        assert_eq!(report.orphan_disputes[0].id, 3);
+    }
+
+    #[test]
+    fn test_exit_code_processing_errors() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,20.0\n",
+        );
+        let mut cli = parse_cli(&[]);
+        cli.transactions_file = Some(input);
+
+        let mut output = Vec::new();
+        let exit_code = run(&cli, &mut output);
+
+        assert_eq!(exit_code, ExitCode::from(EXIT_PROCESSING_ERRORS));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n1,10.0,0,10.0,false\n"
+        );
+    }
+
+    #[test]
+    fn test_exit_code_success_and_fatal_error() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\ndeposit,1,1,10.0\n",
+        );
+        let mut cli = parse_cli(&[]);
+        cli.transactions_file = Some(input);
+        assert_eq!(run(&cli, io::sink()), ExitCode::SUCCESS);
+
+        cli.transactions_file = Some(PathBuf::from("/nonexistent/transactions.csv"));
+        assert_eq!(run(&cli, io::sink()), ExitCode::from(EXIT_FATAL_ERROR));
+    }
+
+    #[test]
+    fn test_manifest() {
+        let contents = "type,client,tx,amount\n\
+                        deposit,1,1,10.0\n\
+                        withdrawal,1,2,20.0\n\
+                        deposit,2,3,5.0\n";
+        let input = temp_file("transactions.csv", contents);
+        let manifest_file = input.with_file_name("manifest.json");
+        let mut cli = parse_cli(&["--manifest", manifest_file.to_str().unwrap()]);
+        cli.transactions_file = Some(input.clone());
+
+        run(&cli, io::sink());
+
+        let manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(manifest_file).unwrap()).unwrap();
+        assert_eq!(manifest["input_file"], input.to_str().unwrap());
+        assert_eq!(manifest["input_size"], contents.len());
+        assert_eq!(manifest["input_sha256"].as_str().unwrap().len(), 64);
+        assert_eq!(manifest["rows_processed"], 3);
+        assert_eq!(manifest["version"], VERSION);
+        assert_eq!(manifest["git_hash"], GIT_HASH);
+        assert!(manifest["started_at"].is_string());
+        assert!(manifest["finished_at"].is_string());
+    }
+
+    #[test]
+    fn test_skip_empty_records() {
+        let cli = parse_cli(&[]);
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    ,,,\n\
+                    withdrawal,1,2,4.0\n\
+                    \n\
+                    \n";
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.total_transactions, 2);
+        assert_eq!(report.failed_transactions, 0);
+        let accounts = engine.get_accounts_statuses();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].available, dec!(6.0));
+    }
+
+    #[test]
+    fn test_skip_comment_rows() {
+        let cli = parse_cli(&["--comment-char", "#"]);
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    # withdrawal,1,2,4.0\n\
+                    #deposit,2,3,1.0\n\
+                    deposit,1,4,1.0\n";
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.total_transactions, 2);
+        let accounts = engine.get_accounts_statuses();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].available, dec!(11.0));
+    }
+
+    #[test]
+    fn test_as_of_tx() {
+        let cli = parse_cli(&["--as-of-tx", "2"]);
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    withdrawal,1,2,4.0\n\
+                    deposit,1,3,100.0\n\
+                    deposit,2,4,1.0\n";
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.total_transactions, 2);
+        let accounts = engine.get_accounts_statuses();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].available, dec!(6.0));
+    }
+
+    #[test]
+    fn test_as_of_tx_later_dispute() {
+        let cli = parse_cli(&["--as-of-tx", "2"]);
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    dispute,1,1,\n\
+                    resolve,1,1,\n\
+                    withdrawal,1,2,4.0\n\
+                    deposit,1,3,100.0\n\
+                    dispute,1,1,\n\
+                    chargeback,1,1,\n";
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        // the dispute and resolve before the cutoff are applied, the ones after it are not
+        assert_eq!(report.total_transactions, 4);
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(6.0));
+        assert_eq!(account.held, dec!(0));
+        assert!(!account.locked);
+    }
+
+    #[test]
+    fn test_missing_amount_is_error_by_default() {
+        let cli = parse_cli(&[]);
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,\n";
+        let mut engine = PaymentEngine::new();
+
+        let result = process_records(&cli, &mut engine, data.as_bytes(), None, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_default_amount() {
+        let cli = parse_cli(&["--default-amount", "5.0"]);
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,\n\
+                    deposit,1,2,\n\
+                    withdrawal,1,3,\n\
+                    dispute,1,1,\n";
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.failed_transactions, 0);
+        let accounts = engine.get_accounts_statuses();
+        assert_eq!(accounts[0].available, dec!(0.0));
+        assert_eq!(accounts[0].held, dec!(5.0));
+        assert_eq!(accounts[0].total, dec!(5.0));
+    }
+
+    #[test]
+    fn test_max_error_rate_exceeded() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,20.0\n\
+             dispute,1,3,\n\
+             deposit,1,4,10.0\n",
+        );
+        let mut cli = parse_cli(&["--max-error-rate", "25"]);
+        cli.transactions_file = Some(input.clone());
+        assert_eq!(
+            run(&cli, io::sink()),
+            ExitCode::from(EXIT_ERROR_RATE_EXCEEDED)
+        );
+
+        let mut cli = parse_cli(&["--max-error-rate", "50"]);
+        cli.transactions_file = Some(input);
+        assert_eq!(
+            run(&cli, io::sink()),
+            ExitCode::from(EXIT_PROCESSING_ERRORS)
+        );
+    }
+
+    #[test]
+    fn test_error_rate_of_empty_input() {
+        let report = ProcessingReport::default();
+        assert_eq!(report.error_rate(), Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_diff_against_baseline() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,20.0\n\
+             deposit,3,3,30.0\n",
+        );
+        let mut cli = parse_cli(&[]);
+        cli.transactions_file = Some(input.clone());
+        let mut baseline = Vec::new();
+        run(&cli, &mut baseline);
+        let baseline_file = input.with_file_name("baseline.csv");
+        fs::write(&baseline_file, baseline).unwrap();
+
+        let corrected_input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,25.0\n\
+             deposit,4,3,30.0\n",
+        );
+        let mut cli = parse_cli(&["--baseline", baseline_file.to_str().unwrap(), "--diff-only"]);
+        cli.transactions_file = Some(corrected_input);
+        let mut output = Vec::new();
+        run(&cli, &mut output);
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked,change\n\
+             2,25.0,0,25.0,false,changed\n\
+             3,30.0,0,30.0,false,removed\n\
+             4,30.0,0,30.0,false,added\n"
+        );
+    }
+
+    #[test]
+    fn test_tiny_buffer_size() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,20.0\n\
+             withdrawal,1,3,2.5\n",
+        );
+        let mut cli = parse_cli(&["--buffer-size", "1"]);
+        cli.transactions_file = Some(input);
+
+        let mut output = Vec::new();
+        assert_eq!(run(&cli, &mut output), ExitCode::SUCCESS);
+
+        let accounts = read_accounts(output.as_slice()).unwrap();
+        let mut balances: Vec<_> = accounts.iter().map(|a| (a.client, a.total)).collect();
+        balances.sort();
+        assert_eq!(balances, vec![(1, dec!(7.5)), (2, dec!(20.0))]);
+    }
+
+    #[test]
+    fn test_expect_accounts() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,20.0\n\
+             dispute,2,2,\n",
+        );
+        let matching = input.with_file_name("matching.csv");
+        fs::write(
+            &matching,
+            "client,available,held,total,locked\n\
+             2,0,20.00,20,false\n\
+             1,10,0,10,false\n",
+        )
+        .unwrap();
+        let mismatching = input.with_file_name("mismatching.csv");
+        fs::write(
+            &mismatching,
+            "client,available,held,total,locked\n\
+             1,10,0,10,false\n\
+             2,20,0,20,false\n",
+        )
+        .unwrap();
+
+        let mut cli = parse_cli(&["--expect-accounts", matching.to_str().unwrap()]);
+        cli.transactions_file = Some(input.clone());
+        assert_eq!(run(&cli, io::sink()), ExitCode::SUCCESS);
+
+        let mut cli = parse_cli(&["--expect-accounts", mismatching.to_str().unwrap()]);
+        cli.transactions_file = Some(input);
+        assert_eq!(
+            run(&cli, io::sink()),
+            ExitCode::from(EXIT_ACCOUNTS_MISMATCH)
+        );
+    }
+
+    #[test]
+    fn test_flexible_short_row() {
+        let cli = parse_cli(&["--flexible"]);
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    dispute,1,1\n";
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.failed_transactions, 0);
+        let accounts = engine.get_accounts_statuses();
+        assert_eq!(accounts[0].held, dec!(10.0));
+
+        let cli = parse_cli(&[]);
+        let mut engine = PaymentEngine::new();
+        assert!(process_records(&cli, &mut engine, data.as_bytes(), None, None).is_err());
+    }
+
+    #[test]
+    fn test_flexible_long_row() {
+        let cli = parse_cli(&["--flexible"]);
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0,extra\n";
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.failed_transactions, 0);
+        let accounts = engine.get_accounts_statuses();
+        assert_eq!(accounts[0].available, dec!(10.0));
+    }
+
+    #[test]
+    fn test_header_only_input() {
+        let input = temp_file("transactions.csv", "type,client,tx,amount\n");
+        let mut cli = parse_cli(&[]);
+        cli.transactions_file = Some(input);
+
+        let mut output = Vec::new();
+        assert_eq!(run(&cli, &mut output), ExitCode::SUCCESS);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n"
+        );
+    }
+
+    #[test]
+    fn test_checksum_file() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,20.0\n",
+        );
+        let checksum_file = input.with_file_name("accounts.csv.sha256");
+        let mut cli = parse_cli(&["--checksum-file", checksum_file.to_str().unwrap()]);
+        cli.transactions_file = Some(input);
+
+        let mut output = Vec::new();
+        run(&cli, &mut output);
+
+        let expected_digest: String = Sha256::digest(&output)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        assert_eq!(
+            fs::read_to_string(checksum_file).unwrap(),
+            format!("{expected_digest}  -\n")
+        );
+    }
+
+    #[test]
+    fn test_stream_updates() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,20.0\n\
+             withdrawal,1,3,2.5\n",
+        );
+        let mut cli = parse_cli(&["--stream-updates"]);
+        cli.transactions_file = Some(input);
+
+        let mut output = Vec::new();
+        run(&cli, &mut output);
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"client\":1,\"available\":\"10.0\",\"held\":\"0\",\"total\":\"10.0\",\"locked\":false}\n\
+             {\"client\":1,\"available\":\"7.5\",\"held\":\"0\",\"total\":\"7.5\",\"locked\":false}\n"
+        );
+    }
+
+    #[test]
+    fn test_locks_report() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             dispute,2,2,\n\
+             chargeback,2,2,\n",
+        );
+        let locks_file = input.with_file_name("locks.csv");
+        let mut cli = parse_cli(&["--locks-report", locks_file.to_str().unwrap()]);
+        cli.transactions_file = Some(input);
+
+        run(&cli, io::sink());
+
+        let report = fs::read_to_string(&locks_file).unwrap();
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "client,locked_by_tx,timestamp");
+        assert!(lines[1].starts_with("2,2,"));
+    }
+
+    #[test]
+    fn test_validate_lifecycle() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             chargeback,1,1,\n\
+             dispute,2,2,\n\
+             dispute,2,2,\n\
+             resolve,2,2,\n\
+             resolve,2,2,\n\
+             dispute,1,3,\n",
+        );
+        let lifecycle_file = input.with_file_name("lifecycle.csv");
+        let mut cli = parse_cli(&["--validate-lifecycle", lifecycle_file.to_str().unwrap()]);
+        cli.transactions_file = Some(input);
+
+        run(&cli, io::sink());
+
+        // the dispute of the unknown transaction is not a lifecycle violation
+        assert_eq!(
+            fs::read_to_string(&lifecycle_file).unwrap(),
+            "type,client,tx,violation\n\
+             chargeback,1,1,not_disputed\n\
+             dispute,2,2,already_disputed\n\
+             resolve,2,2,not_disputed\n"
+        );
+    }
+
+    #[test]
+    fn test_open_disputes_report() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.5\n\
+             dispute,1,1,\n\
+             dispute,2,2,\n\
+             resolve,1,1,\n",
+        );
+        let open_disputes_file = input.with_file_name("open-disputes.csv");
+        let mut cli = parse_cli(&[
+            "--open-disputes-report",
+            open_disputes_file.to_str().unwrap(),
+        ]);
+        cli.transactions_file = Some(input);
+
+        run(&cli, io::sink());
+
+        assert_eq!(
+            fs::read_to_string(&open_disputes_file).unwrap(),
+            "tx,client,amount\n2,2,5.5\n"
+        );
+    }
+
+    #[test]
+    fn test_parallel_files() {
+        let first = temp_file(
+            "first.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,2.5\n",
+        );
+        let second = temp_file(
+            "second.csv",
+            "type,client,tx,amount\n\
+             deposit,2,3,5.0\n\
+             deposit,3,4,1.0\n",
+        );
+        let cli = Cli::parse_from([
+            "transactions-processor".as_ref(),
+            "--parallel-files".as_ref(),
+            first.as_os_str(),
+            second.as_os_str(),
+        ]);
+
+        let mut output = Vec::new();
+        let (report, payment_engine) = process_files_in_parallel(&cli, None).unwrap();
+        let mut accounts = payment_engine.get_accounts_statuses();
+        accounts.sort_by_key(|a| a.client);
+        write_accounts(&mut output, &accounts, &OutputOptions::default()).unwrap();
+
+        assert_eq!(report.total_transactions, 4);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n\
+             1,7.5,0,7.5,false\n\
+             2,5.0,0,5.0,false\n\
+             3,1.0,0,1.0,false\n"
+        );
+    }
+
+    #[test]
+    fn test_parallel_files_transaction_index() {
+        let first = temp_file(
+            "first.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n",
+        );
+        let second = temp_file(
+            "second.csv",
+            "type,client,tx,amount\n\
+             deposit,2,2,5.0\n",
+        );
+        let cli = Cli::parse_from([
+            "transactions-processor".as_ref(),
+            "--parallel-files".as_ref(),
+            "--transaction-index".as_ref(),
+            "mmap".as_ref(),
+            first.as_os_str(),
+            second.as_os_str(),
+        ]);
+
+        let (_, mut payment_engine) = process_files_in_parallel(&cli, None).unwrap();
+
+        // the merged transactions are kept in the index, not in memory
+        assert_eq!(
+            payment_engine.config().transaction_store,
+            TransactionStoreKind::Mmap
+        );
+        assert_eq!(payment_engine.memory_usage().transactions, 2);
+        payment_engine
+            .process_transaction(Transaction::new(2, 2, TransactionType::Dispute))
+            .unwrap();
+        assert_eq!(payment_engine.get_account(2, None).unwrap().held, dec!(5.0));
+    }
+
+    #[test]
+    fn test_parallel_files_overlapping_clients() {
+        let first = temp_file(
+            "first.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n",
+        );
+        let second = temp_file(
+            "second.csv",
+            "type,client,tx,amount\n\
+             deposit,2,2,5.0\n\
+             deposit,1,3,1.0\n",
+        );
+        let cli = Cli::parse_from([
+            "transactions-processor".as_ref(),
+            "--parallel-files".as_ref(),
+            first.as_os_str(),
+            second.as_os_str(),
+        ]);
+
+        let err = process_files_in_parallel(&cli, None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<MergeError>(),
+            Some(MergeError::ClientCollision(1))
+        ));
+        assert_eq!(run(&cli, io::sink()), ExitCode::from(EXIT_FATAL_ERROR));
+    }
+
+    #[test]
+    fn test_on_undisputed() {
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    resolve,1,1,\n\
+                    chargeback,1,1,\n";
+
+        let cli = parse_cli(&["--on-undisputed", "warn"]);
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig::from(&cli));
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+        assert_eq!(report.failed_transactions, 2);
+
+        let cli = parse_cli(&["--on-undisputed", "ignore"]);
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig::from(&cli));
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+        assert_eq!(report.failed_transactions, 0);
+
+        let cli = parse_cli(&["--on-undisputed", "error"]);
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig::from(&cli));
+        let err = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<PaymentEngineError>(),
+            Some(PaymentEngineError::NotDisputed(1))
+        ));
+    }
+
+    #[test]
+    fn test_normalize_clients() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,42,1,1.0\n\
+             deposit,7,2,2.0\n\
+             deposit,1000,3,3.0\n\
+             deposit,7,4,4.0\n",
+        );
+        let client_map = input.with_file_name("clients.csv");
+        let mut cli = parse_cli(&[
+            "--normalize-clients",
+            "--client-map",
+            client_map.to_str().unwrap(),
+        ]);
+        cli.transactions_file = Some(input);
+
+        let mut output = Vec::new();
+        assert_eq!(run(&cli, &mut output), ExitCode::SUCCESS);
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n\
+             1,6.0,0,6.0,false\n\
+             2,1.0,0,1.0,false\n\
+             3,3.0,0,3.0,false\n"
+        );
+        assert_eq!(
+            fs::read_to_string(client_map).unwrap(),
+            "original,new\n7,1\n42,2\n1000,3\n"
+        );
+    }
+
+    #[test]
+    fn test_zip_archive() {
+        let mut archive = ZipWriter::new(io::Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default();
+        archive.start_file("2024-01-02.csv", options).unwrap();
+        archive
+            .write_all(b"type,client,tx,amount\nwithdrawal,1,3,4.0\ndispute,2,2,\n")
+            .unwrap();
+        archive.start_file("README.txt", options).unwrap();
+        archive.write_all(b"daily export").unwrap();
+        archive.start_file("2024-01-01.csv", options).unwrap();
+        archive
+            .write_all(b"type,client,tx,amount\ndeposit,1,1,10.0\ndeposit,2,2,5.0\n")
+            .unwrap();
+        let archive = archive.finish().unwrap().into_inner();
+
+        let input = temp_file("transactions.zip", "");
+        fs::write(&input, archive).unwrap();
+        let mut cli = parse_cli(&[]);
+        cli.transactions_file = Some(input);
+
+        let mut output = Vec::new();
+        assert_eq!(run(&cli, &mut output), ExitCode::SUCCESS);
+
+        let output = String::from_utf8(output).unwrap();
+        let mut lines = output.lines().collect::<Vec<_>>();
+        lines.sort();
+        assert_eq!(
+            lines,
+            [
+                "1,6.0,0,6.0,false",
+                "2,0.0,5.0,5.0,false",
+                "client,available,held,total,locked"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_windowed_deltas() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             withdrawal,1,3,2.0\n\
+             deposit,1,4,1.5\n\
+             dispute,1,1,\n\
+             deposit,3,5,7.0\n\
+             deposit,1,6,100.0\n",
+        );
+        let mut cli = parse_cli(&["--since-tx", "3", "--until-tx", "5"]);
+        cli.transactions_file = Some(input);
+
+        let mut output = Vec::new();
+        assert_eq!(run(&cli, &mut output), ExitCode::SUCCESS);
+
+        // the dispute of tx 1 is windowed by its position, so only the later state includes it
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,delta_available,delta_held,delta_total\n\
+             1,-10.5,10.0,-0.5\n\
+             2,0.0,0,0.0\n\
+             3,7.0,0,7.0\n"
+        );
+    }
+
+    #[test]
+    fn test_require_monotonic_tx() {
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    deposit,1,3,10.0\n\
+                    dispute,1,1,\n\
+                    withdrawal,1,4,1.0\n\
+                    deposit,2,2,5.0\n";
+
+        let cli = parse_cli(&[]);
+        let mut engine = PaymentEngine::new();
+        process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        let cli = parse_cli(&["--require-monotonic-tx"]);
+        let mut engine = PaymentEngine::new();
+        let err = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "transaction 2 is not greater than the previous transaction 4"
+        );
+
+        // a row failing due to its amount is still out of order
+        let data = "type,client,tx,amount\n\
+                    deposit,1,3,10.0\n\
+                    deposit,1,2,-1.0\n";
+        let mut engine = PaymentEngine::new();
+        let err = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "transaction 2 is not greater than the previous transaction 3"
+        );
+    }
+
+    #[test]
+    fn test_dump_state() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,2.5\n\
+             dispute,1,1,\n",
+        );
+        let dump_file = input.with_file_name("state.json");
+        let mut cli = parse_cli(&["--dump-state", dump_file.to_str().unwrap()]);
+        cli.transactions_file = Some(input);
+
+        assert_eq!(run(&cli, io::sink()), ExitCode::SUCCESS);
+
+        let state: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dump_file).unwrap()).unwrap();
+        assert_eq!(
+            state,
+            serde_json::json!({
+                "accounts": [
+                    {"client": 1, "available": "-2.5", "held": "10.0", "total": "7.5", "locked": false},
+                ],
+                "transactions": [
+                    {
+                        "client": 1,
+                        "id": 1,
+                        "currency": null,
+                        "is_disputed": true,
+                        "is_pending": false,
+                        "lifecycle": ["disputed"],
+                        "type": {"deposit": {"amount": "10.0"}},
+                    },
+                    {
+                        "client": 1,
+                        "id": 2,
+                        "currency": null,
+                        "is_disputed": false,
+                        "is_pending": false,
+                        "lifecycle": [],
+                        "type": {"withdrawal": {"amount": "2.5"}},
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_decimal_separator() {
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,\"1.234,56\"\n\
+                    withdrawal,1,2,\"0,5\"\n";
+        let cli = parse_cli(&["--decimal-separator", "comma"]);
+        let mut engine = PaymentEngine::new();
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+        assert_eq!(report.failed_transactions, 0);
+        assert_eq!(
+            engine.get_account(1, None).unwrap().available,
+            dec!(1234.06)
+        );
+
+        for (amount, expected) in [
+            ("1,5", Some("1.5")),
+            ("1.234,5", Some("1234.5")),
+            ("-12.345.678,9", Some("-12345678.9")),
+            ("42", Some("42")),
+            ("", Some("")),
+            // a dot without a decimal comma is ambiguous
+            ("1.5", None),
+            ("1.234", None),
+            ("12.34,5", None),
+            ("1.234,5,6", None),
+        ] {
+            assert_eq!(delocalize_amount(amount).as_deref(), expected, "{amount}");
+        }
+
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,1.5\n";
+        let mut engine = PaymentEngine::new();
+        let err = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(CsvTransactionError::InvalidLocalizedAmount { line: 2, amount }) if amount == "1.5"
+        ));
+
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,1234.56\n";
+        let cli = parse_cli(&["--decimal-separator", "dot"]);
+        let mut engine = PaymentEngine::new();
+        process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+        assert_eq!(
+            engine.get_account(1, None).unwrap().available,
+            dec!(1234.56)
+        );
+    }
+
+    #[test]
+    fn test_max_duration() {
+        let mut data = String::from("type,client,tx,amount\n");
+        for id in 1..=10 * DEADLINE_CHECK_INTERVAL {
+            data.push_str(&format!("deposit,1,{id},1.0\n"));
+        }
+        let input = temp_file("transactions.csv", &data);
+        let mut cli = parse_cli(&["--max-duration", "0"]);
+        cli.transactions_file = Some(input);
+
+        let mut output = Vec::new();
+        assert_eq!(run(&cli, &mut output), ExitCode::from(EXIT_TIMED_OUT));
+
+        // the deadline has already passed at the first check
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!(
+                "client,available,held,total,locked\n1,{DEADLINE_CHECK_INTERVAL}.0,0,{DEADLINE_CHECK_INTERVAL}.0,false\n"
+            )
+        );
+
+        let mut engine = PaymentEngine::new();
+        let deadline = Instant::now() + Duration::from_secs(3600);
+        let report =
+            process_records(&cli, &mut engine, data.as_bytes(), None, Some(deadline)).unwrap();
+        assert!(!report.timed_out);
+        assert_eq!(report.total_transactions, 10 * DEADLINE_CHECK_INTERVAL);
+    }
+
+    #[test]
+    fn test_invalid_amounts() {
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,-10.0\n\
+                    withdrawal,1,2,-20.0\n\
+                    deposit,1,3,1.00001\n\
+                    deposit,1,4,5.0\n";
+        let cli = parse_cli(&[]);
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.total_transactions, 4);
+        assert_eq!(report.failed_transactions, 3);
+        assert_eq!(engine.get_account(1, None).unwrap().total, dec!(5.0));
+    }
+
+    #[test]
+    fn test_client_out_of_range() {
+        let data = "type,client,tx,amount\n\
+                    deposit,70000,1,10.0\n\
+                    deposit,1,2,5.0\n";
+        let cli = parse_cli(&[]);
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.total_transactions, 2);
+        assert_eq!(report.failed_transactions, 1);
+        assert_eq!(engine.get_account(1, None).unwrap().total, dec!(5.0));
+    }
+
+    #[test]
+    fn test_allow_deny_clients() {
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,10.0\n\
+                    deposit,2,2,20.0\n\
+                    deposit,3,3,30.0\n\
+                    deposit,4,4,40.0\n";
+        let allow_file = temp_file("allow.txt", "1\n3\n\n4\n");
+        let deny_file = temp_file("deny.txt", "2\n3\n");
+        let cli = parse_cli(&[
+            "--allow-clients",
+            allow_file.to_str().unwrap(),
+            "--deny-clients",
+            deny_file.to_str().unwrap(),
+        ]);
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.total_transactions, 2);
+        assert_eq!(report.failed_transactions, 0);
+        let mut clients = engine
+            .get_accounts_statuses()
+            .iter()
+            .map(|account| account.client)
+            .collect::<Vec<_>>();
+        clients.sort();
+        // 2 is only denied, 3 is both allowed and denied
+        assert_eq!(clients, [1, 4]);
+
+        let invalid_file = temp_file("invalid.txt", "1\nabc\n");
+        let result = Cli::try_parse_from([
+            "transactions-processor",
+            "--deny-clients",
+            invalid_file.to_str().unwrap(),
+            "transactions.csv",
+        ]);
+        assert!(result.unwrap_err().to_string().contains("invalid.txt:2"));
+    }
 
     #[test]
-    fn test_deserialize_deposit() {
+    fn test_atomic_per_client() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.0\n\
+             withdrawal,2,3,7.0\n\
+             deposit,3,4,-1.0\n",
+        );
+
+        // the vec store keeps the accounts sorted by client
+        let mut cli = parse_cli(&["--atomic-per-client", "--client-store", "vec"]);
+        cli.transactions_file = Some(input.clone());
+        let mut output = Vec::new();
+        let exit_code = run(&cli, &mut output);
+
+        assert_eq!(exit_code, ExitCode::from(EXIT_PROCESSING_ERRORS));
+        // client 3 has no account as its only transaction failed
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked,had_errors\n\
+             1,10.0,0,10.0,false,false\n\
+             2,5.0,0,5.0,false,true\n"
+        );
+
+        let mut cli = parse_cli(&["--atomic-per-client", "--exclude-failed-clients"]);
+        cli.transactions_file = Some(input);
+        let mut output = Vec::new();
+        run(&cli, &mut output);
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n\
+             1,10.0,0,10.0,false\n"
+        );
+    }
+
+    #[test]
+    fn test_latency_stats() {
         let data = "type,client,tx,amount\n\
-                    deposit,1,1001,42.5\n";
-        let mut reader = ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(data.as_bytes());
-        let mut iter = reader.deserialize::<CsvTransaction>();
-        let tx = iter.next().unwrap().unwrap();
-        assert_eq!(tx.transaction_type, CsvTransactionType::Deposit);
-        assert_eq!(tx.client, 1);
-        assert_eq!(tx.tx, 1001);
-        assert_eq!(tx.amount, Some(dec!(42.5)));
+                    deposit,1,1,10.0\n\
+                    withdrawal,1,2,2.5\n\
+                    dispute,1,1,\n\
+                    resolve,1,1,\n";
+        let cli = parse_cli(&["--latency-stats"]);
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+        let mut output = Vec::new();
+        report.write_latency_stats(&mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "transaction processing latency (4 transactions):");
+        for (line, percentile) in lines[1..].iter().zip(["p50", "p95", "p99"]) {
+            let value = line
+                .trim()
+                .strip_prefix(&format!("{percentile}: "))
+                .and_then(|value| value.strip_suffix(" ns"))
+                .unwrap();
+            assert!(value.parse::<i64>().unwrap() >= 0);
+        }
+
+        let report = process_records(
+            &parse_cli(&[]),
+            &mut PaymentEngine::new(),
+            data.as_bytes(),
+            None,
+            None,
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        report.write_latency_stats(&mut output).unwrap();
+        assert!(output.is_empty());
     }
 
     #[test]
-    fn test_deserialize_withdrawal() {
+    fn test_amount_overrides() {
         let data = "type,client,tx,amount\n\
-                    withdrawal,2,1002,10.0\n";
-        let mut reader = ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(data.as_bytes());
-        let mut iter = reader.deserialize::<CsvTransaction>();
-        let tx = iter.next().unwrap().unwrap();
-        assert_eq!(tx.transaction_type, CsvTransactionType::Withdrawal);
-        assert_eq!(tx.client, 2);
-        assert_eq!(tx.tx, 1002);
-        assert_eq!(tx.amount, Some(dec!(10.0)));
+                    deposit,1,1,1000.0\n\
+                    withdrawal,1,2,2.5\n\
+                    dispute,1,1,\n";
+        let overrides_file = temp_file("overrides.csv", "tx,amount\n1,10.0\n3,5.0\n");
+        let cli = parse_cli(&["--amount-overrides", overrides_file.to_str().unwrap()]);
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.failed_transactions, 0);
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(-2.5));
+        assert_eq!(account.held, dec!(10.0));
+        assert_eq!(account.total, dec!(7.5));
+        // the dispute of tx 1 has no amount which could be overridden
+        assert_eq!(report.applied_overrides, HashSet::from([1]));
+    }
+
+    #[test]
+    fn test_amount_overrides_forgotten() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,1000.0\n\
+             deposit,1,2,2.0\n\
+             deposit,1,3,0.5\n",
+        );
+        let overrides_file = temp_file("overrides.csv", "tx,amount\n1,50.0\n4,5.0\n");
+        let mut cli = parse_cli(&[
+            "--amount-overrides",
+            overrides_file.to_str().unwrap(),
+            "--forget-settled-after",
+            "1",
+        ]);
+        cli.transactions_file = Some(input);
+
+        let (exit_code, logs) = run_logged(&cli);
+
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+        // tx 1 was processed with the override and then forgotten, tx 4 was never processed
+        assert!(!logs.contains("transaction_id=1"));
+        assert!(logs.contains("transaction_id=4"));
+    }
+
+    /// Buffer collecting the logged messages.
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Runs with the given arguments and returns the exit code and the messages logged at the warn
+    /// level or above.
+    fn run_logged(cli: &Cli) -> (ExitCode, String) {
+        let logs = LogBuffer::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::WARN)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        let exit_code = tracing::subscriber::with_default(subscriber, || run(cli, io::sink()));
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        (exit_code, logs)
+    }
+
+    #[test]
+    fn test_quiet() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,20.0\n\
+             dispute,1,3,\n",
+        );
+
+        let mut cli = parse_cli(&[]);
+        cli.transactions_file = Some(input.clone());
+        let (exit_code, logs) = run_logged(&cli);
+        assert_eq!(exit_code, ExitCode::from(EXIT_PROCESSING_ERRORS));
+        assert_eq!(logs.matches("transaction processing failed").count(), 2);
+        assert!(logs.contains("some transactions failed to be processed"));
+
+        let mut cli = parse_cli(&["--quiet"]);
+        cli.transactions_file = Some(input);
+        let (exit_code, logs) = run_logged(&cli);
+        assert_eq!(exit_code, ExitCode::from(EXIT_PROCESSING_ERRORS));
+        assert!(!logs.contains("transaction processing failed"));
+        assert!(logs.contains("some transactions failed to be processed"));
+        assert!(logs.contains("failed=2 total=3"));
+    }
+
+    #[test]
+    fn test_jsonl_input() {
+        let csv = "type,client,tx,amount\n\
+                   deposit,1,1,100.0\n\
+                   withdrawal,1,2,25.5\n\
+                   deposit,2,3,7.1234\n\
+                   dispute,2,3,\n\
+                   withdrawal,2,4,1.0\n";
+        let jsonl = r#"{"type":"deposit","client":1,"tx":1,"amount":"100.0"}
+{"type":"withdrawal","client":1,"tx":2,"amount":"25.5"}
+
+{"type":"deposit","client":2,"tx":3,"amount":"7.1234"}
+{"type":"dispute","client":2,"tx":3}
+{"type":"withdrawal","client":2,"tx":4,"amount":"1.0"}
+"#;
+        let balances = |engine: &PaymentEngine| {
+            let mut accounts = engine
+                .get_accounts_statuses()
+                .into_iter()
+                .map(|a| (a.client, a.available, a.held, a.total, a.locked))
+                .collect::<Vec<_>>();
+            accounts.sort();
+            accounts
+        };
+
+        let mut csv_engine = PaymentEngine::new();
+        let csv_report =
+            process_records(&parse_cli(&[]), &mut csv_engine, csv.as_bytes(), None, None).unwrap();
+        let mut jsonl_engine = PaymentEngine::new();
+        let cli = parse_cli(&["--input-format", "jsonl"]);
+        let jsonl_report =
+            process_records(&cli, &mut jsonl_engine, jsonl.as_bytes(), None, None).unwrap();
+
+        assert_eq!(jsonl_report.total_transactions, 5);
+        assert_eq!(jsonl_report.failed_transactions, 1);
+        assert_eq!(
+            jsonl_report.failed_transactions,
+            csv_report.failed_transactions
+        );
+        assert_eq!(balances(&jsonl_engine), balances(&csv_engine));
+
+        let invalid = "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"1.0\"}\n{\"type\":";
+        let err = process_records(
+            &cli,
+            &mut PaymentEngine::new(),
+            invalid.as_bytes(),
+            None,
+            None,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("line 2"));
     }
 
     #[test]
-    fn test_deserialize_dispute() {
+    fn test_warn_held() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             deposit,2,2,5.5\n\
+             deposit,2,3,1.0\n\
+             deposit,3,4,2.0\n\
+             dispute,1,1,\n\
+             resolve,1,1,\n\
+             dispute,2,2,\n\
+             dispute,3,4,\n\
+             chargeback,3,4,\n",
+        );
+        let mut cli = parse_cli(&["--warn-held"]);
+        cli.transactions_file = Some(input);
+
+        let (exit_code, logs) = run_logged(&cli);
+
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+        assert_eq!(
+            logs.matches("account still holds funds at the end of the run")
+                .count(),
+            1
+        );
+        assert!(logs.contains("client=2 held=5.5"));
+    }
+
+    #[test]
+    fn test_scientific_notation() {
         let data = "type,client,tx,amount\n\
-                    dispute,3,1003,\n";
-        let mut reader = ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(data.as_bytes());
-        let mut iter = reader.deserialize::<CsvTransaction>();
-        let tx = iter.next().unwrap().unwrap();
-        assert_eq!(tx.transaction_type, CsvTransactionType::Dispute);
-        assert_eq!(tx.client, 3);
-        assert_eq!(tx.tx, 1003);
-        assert_eq!(tx.amount, None);
+                    deposit,1,1,1e3\n\
+                    deposit,1,2,1.5E2\n\
+                    withdrawal,1,3, 2.5e-1 \n";
+        let cli = parse_cli(&["--allow-scientific-notation"]);
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.failed_transactions, 0);
+        assert_eq!(engine.get_account(1, None).unwrap().total, dec!(1149.75));
+
+        let result = process_records(
+            &parse_cli(&[]),
+            &mut PaymentEngine::new(),
+            data.as_bytes(),
+            None,
+            None,
+        );
+        assert!(
+            format!("{:#}", result.unwrap_err()).contains("amount 1e3 is in scientific notation")
+        );
+    }
+
+    #[test]
+    fn test_journal_dir() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,2.5\n\
+             withdrawal,1,3,100.0\n\
+             dispute,1,1,\n\
+             resolve,1,1,\n\
+             deposit,2,4,1.0\n",
+        );
+        let journal_dir = input.with_file_name("journal");
+        let mut cli = parse_cli(&["--journal-dir", journal_dir.to_str().unwrap()]);
+        cli.transactions_file = Some(input);
+
+        run(&cli, io::sink());
+
+        assert_eq!(
+            fs::read_to_string(journal_dir.join("1.csv")).unwrap(),
+            "tx,type,amount,available_after,held_after,total_after\n\
+             1,deposit,10.0,10.0,0,10.0\n\
+             2,withdrawal,2.5,7.5,0,7.5\n\
+             1,dispute,10.0,-2.5,10.0,7.5\n\
+             1,resolve,10.0,7.5,0.0,7.5\n"
+        );
+        assert!(journal_dir.join("2.csv").exists());
+    }
+
+    #[test]
+    fn test_transaction_index_backends() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount,currency\n\
+             deposit,1,1,10.0,\n\
+             deposit,1,2,5.0,EUR\n\
+             withdrawal,1,3,2.5,\n\
+             dispute,1,1,,\n\
+             resolve,1,1,,\n\
+             dispute,1,2,,EUR\n\
+             chargeback,1,2,,EUR\n\
+             pending,2,5000,7.0,\n\
+             settle,2,5000,,\n\
+             deposit,2,4,1.0,\n\
+             dispute,2,4,,\n\
+             dispute,2,99,,\n",
+        );
+
+        let outputs = ["memory", "mmap"].map(|index| {
+            let mut cli = parse_cli(&["--transaction-index", index, "--client-store", "vec"]);
+            cli.transactions_file = Some(input.clone());
+            let mut output = Vec::new();
+            let report = process_file(&cli, &mut output).unwrap();
+            assert_eq!(report.failed_transactions, 1);
+            String::from_utf8(output).unwrap()
+        });
+
+        assert_eq!(outputs[0], outputs[1]);
+        assert!(
+            outputs[0].contains("2,,7.0,1.0,8.0,false"),
+            "{}",
+            outputs[0]
+        );
+    }
+
+    #[test]
+    fn test_invalid_headers() {
+        let cli = parse_cli(&[]);
+        let mut engine = PaymentEngine::new();
+
+        let data = "type,client,amount\n\
+                    deposit,1,10.0\n";
+        let err = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(CsvTransactionError::InvalidHeaders { missing, duplicated })
+                if missing == &["tx"] && duplicated.is_empty()
+        ));
+
+        let data = "type,client,tx,amount,amount\n\
+                    deposit,1,1,10.0,20.0\n";
+        let err = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid header (missing columns: [], duplicated columns: [amount])"
+        );
+        assert!(engine.get_account(1, None).is_none());
+    }
+
+    #[test]
+    fn test_max_rows_per_file() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,1.0\n\
+             deposit,2,2,2.0\n\
+             deposit,3,3,3.0\n\
+             deposit,4,4,4.0\n\
+             deposit,5,5,5.0\n",
+        );
+        let prefix = input.with_file_name("accounts");
+        let mut cli = parse_cli(&[
+            "--client-store",
+            "vec",
+            "--max-rows-per-file",
+            "2",
+            "--output",
+            prefix.to_str().unwrap(),
+        ]);
+        cli.transactions_file = Some(input);
+
+        let mut stdout = Vec::new();
+        process_file(&cli, &mut stdout).unwrap();
+
+        assert!(stdout.is_empty());
+        let part = |part| fs::read_to_string(RollingWriter::part_path(&prefix, part)).unwrap();
+        assert_eq!(
+            part(1),
+            "client,available,held,total,locked\n\
+             1,1.0,0,1.0,false\n\
+             2,2.0,0,2.0,false\n"
+        );
+        assert_eq!(
+            part(2),
+            "client,available,held,total,locked\n\
+             3,3.0,0,3.0,false\n\
+             4,4.0,0,4.0,false\n"
+        );
+        assert_eq!(
+            part(3),
+            "client,available,held,total,locked\n\
+             5,5.0,0,5.0,false\n"
+        );
+        assert!(!RollingWriter::part_path(&prefix, 4).exists());
+    }
+
+    #[test]
+    fn test_seed_accounts() {
+        let seed = temp_file(
+            "accounts.csv",
+            "client,available,held,total,locked\n\
+             1,10.0,0,10.0,true\n\
+             2,5.0,1.5,6.5,false\n",
+        );
+        let mut cli = parse_cli(&["--seed-accounts", seed.to_str().unwrap()]);
+        cli.transactions_file = Some(temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,1.0\n\
+             deposit,2,2,1.0\n",
+        ));
+
+        let engine = seeded_engine(&cli).unwrap();
+        let account = engine.get_account(1, None).unwrap();
+        assert!(account.locked);
+        assert_eq!(account.total, dec!(10.0));
+
+        let mut output = Vec::new();
+        let report = process_file(&cli, &mut output).unwrap();
+        assert_eq!(report.failed_transactions, 1);
+        let mut rows = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        rows.sort();
+        assert_eq!(rows, ["1,10.0,0,10.0,true", "2,6.0,1.5,7.5,false"]);
+    }
+
+    #[test]
+    fn test_report_json() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,10.0\n\
+             withdrawal,1,2,50.0\n\
+             deposit,2,3,5.0\n\
+             dispute,2,3,\n\
+             chargeback,2,3,\n\
+             deposit,2,4,1.0\n\
+             withdrawal,2,5,1.0\n\
+             deposit,70000,6,1.0\n\
+             deposit,3,7,1.0\n",
+        );
+        let report_file = input.with_file_name("report.json");
+        let mut cli = parse_cli(&["--report-json", report_file.to_str().unwrap()]);
+        cli.transactions_file = Some(input);
+
+        process_file(&cli, io::sink()).unwrap();
+
+        let mut report: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(report_file).unwrap()).unwrap();
+        assert!(report["elapsed_ms"].is_u64());
+        report.as_object_mut().unwrap().remove("elapsed_ms");
+        assert_eq!(
+            report,
+            serde_json::json!({
+                "total_rows": 9,
+                "processed": 5,
+                "failed": 4,
+                "failed_by_error": {
+                    "AccountLocked": 2,
+                    "ClientOutOfRange": 1,
+                    "InsufficientFunds": 1,
+                },
+                "distinct_clients": 3,
+                "locked_accounts": 1,
+                "dispute_targets": {
+                    "deposits": 1,
+                    "withdrawals": 0,
+                    "missing": 0,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_abs_amounts() {
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1,100.0\n\
+                    withdrawal,1,2,-50\n\
+                    withdrawal,1,3,20.5\n\
+                    deposit,2,4,-7.25\n";
+        let cli = parse_cli(&["--abs-amounts"]);
+        let mut engine = PaymentEngine::new();
+
+        let report = process_records(&cli, &mut engine, data.as_bytes(), None, None).unwrap();
+
+        assert_eq!(report.failed_transactions, 0);
+        assert_eq!(engine.get_account(1, None).unwrap().total, dec!(29.5));
+        assert_eq!(engine.get_account(2, None).unwrap().total, dec!(7.25));
+
+        // negative amounts are rejected by default
+        let mut engine = PaymentEngine::new();
+        let report =
+            process_records(&parse_cli(&[]), &mut engine, data.as_bytes(), None, None).unwrap();
+        assert_eq!(report.failed_transactions, 2);
+        assert_eq!(engine.get_account(1, None).unwrap().total, dec!(79.5));
+    }
+
+    #[test]
+    fn test_opening_balances() {
+        let balances = temp_file(
+            "balances.csv",
+            "client,opening_balance\n\
+             1,100\n\
+             2,-5.5\n",
+        );
+        let mut cli = parse_cli(&["--opening-balances", balances.to_str().unwrap()]);
+        cli.transactions_file = Some(temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             withdrawal,1,1,30\n\
+             deposit,2,2,10.0\n",
+        ));
+
+        let mut engine = seeded_engine(&cli).unwrap();
+        let report = process_records(
+            &cli,
+            &mut engine,
+            File::open(cli.transactions_file().unwrap()).unwrap(),
+            None,
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(report.failed_transactions, 0);
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(70));
+        assert_eq!(account.held, dec!(0));
+        assert_eq!(account.total, dec!(70));
+        assert!(!account.locked);
+        assert_eq!(engine.get_account(2, None).unwrap().total, dec!(4.5));
+        // the opening balances are not transactions which could be disputed, only the two of the
+        // input are stored
+        assert_eq!(engine.memory_usage().transactions, 2);
+        assert!(matches!(
+            engine.process_transaction(Transaction::new(2, 0, TransactionType::Dispute)),
+            Err(PaymentEngineError::TransactionNotFound(0))
+        ));
+        assert_eq!(engine.get_account(2, None).unwrap().held, dec!(0));
+    }
+
+    #[test]
+    fn test_snapshot_dir() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,1.0\n\
+             deposit,1,2,2.0\n\
+             deposit,2,3,3.0\n\
+             deposit,2,4,4.0\n\
+             deposit,2,5,5.0\n",
+        );
+        let snapshot_dir = input.with_file_name("snapshots");
+        let mut cli = parse_cli(&[
+            "--client-store",
+            "vec",
+            "--snapshot-dir",
+            snapshot_dir.to_str().unwrap(),
+            "--snapshot-interval",
+            "2",
+        ]);
+        cli.transactions_file = Some(input);
+
+        process_file(&cli, Vec::new()).unwrap();
+
+        let snapshots = SnapshotWriter::snapshots(&snapshot_dir).unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(
+            fs::read_to_string(&snapshots[0]).unwrap(),
+            "client,available,held,total,locked\n\
+             1,3.0,0,3.0,false\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&snapshots[1]).unwrap(),
+            "client,available,held,total,locked\n\
+             1,3.0,0,3.0,false\n\
+             2,7.0,0,7.0,false\n"
+        );
+    }
+
+    #[test]
+    fn test_windowed_deltas_normalize_clients() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,5,1,10.0\n\
+             deposit,9,2,5.0\n\
+             deposit,9,3,1.0\n\
+             deposit,7,4,2.0\n",
+        );
+        let mut cli = parse_cli(&["--since-tx", "3", "--normalize-clients"]);
+        cli.transactions_file = Some(input);
+
+        let mut output = Vec::new();
+        assert_eq!(run(&cli, &mut output), ExitCode::SUCCESS);
+
+        // clients 5, 7 and 9 are renumbered to 1, 2 and 3 after the deltas are computed
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,delta_available,delta_held,delta_total\n\
+             1,0.0,0,0.0\n\
+             2,2.0,0,2.0\n\
+             3,1.0,0,1.0\n"
+        );
+    }
+
+    #[test]
+    fn test_output_file() {
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,1.0\n",
+        );
+        let output_file = input.with_file_name("accounts.csv");
+
+        for (format, expected) in [
+            (
+                "csv",
+                "client,available,held,total,locked\n1,1.0,0,1.0,false\n",
+            ),
+            ("status-only", "client,locked\n1,false\n"),
+        ] {
+            let mut cli = parse_cli(&[
+                "--format",
+                format,
+                "--output",
+                output_file.to_str().unwrap(),
+            ]);
+            cli.transactions_file = Some(input.clone());
+
+            let mut stdout = Vec::new();
+            process_file(&cli, &mut stdout).unwrap();
+
+            assert!(stdout.is_empty());
+            assert_eq!(fs::read_to_string(&output_file).unwrap(), expected);
+        }
+
+        let mut cli = parse_cli(&[
+            "--format",
+            "table",
+            "--output",
+            output_file.to_str().unwrap(),
+        ]);
+        cli.transactions_file = Some(input);
+        let mut stdout = Vec::new();
+        process_file(&cli, &mut stdout).unwrap();
+        assert!(stdout.is_empty());
+        let table = fs::read_to_string(&output_file).unwrap();
+        assert!(table.contains("client"));
+        // no colors in files
+        assert!(!table.contains('\u{1b}'));
+    }
+
+    #[test]
+    fn test_missing_transactions_file() {
+        let mut cli = parse_cli(&[]);
+        cli.transactions_file = None;
+
+        let err = process_file(&cli, &mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "the transactions file is required");
+    }
+
+    #[test]
+    fn test_validate_max_problems() {
+        let result = Cli::try_parse_from([
+            "transactions-processor",
+            "validate",
+            "--max-problems",
+            "0",
+            "transactions.csv",
+        ]);
+        assert!(result.is_err());
+
+        let input = temp_file(
+            "transactions.csv",
+            "type,client,tx,amount\n\
+             deposit,1,1,abc\n",
+        );
+        assert_eq!(validate(&input, 1), ExitCode::from(EXIT_PROCESSING_ERRORS));
+    }
+
+    #[test]
+    fn test_duplicate_client_aliases() {
+        let aliases = temp_file(
+            "aliases.csv",
+            "client,external_account\n\
+             1,ACC-1\n\
+             2,ACC-2\n\
+             1,ACC-3\n",
+        );
+        let result = Cli::try_parse_from([
+            "transactions-processor",
+            "--client-alias-file",
+            aliases.to_str().unwrap(),
+            "transactions.csv",
+        ]);
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("client 1 has several external accounts")
+        );
     }
 }
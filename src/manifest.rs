@@ -0,0 +1,54 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Provenance information about a single run of the processor.
+#[derive(Serialize, Debug)]
+pub struct Manifest {
+    pub input_file: PathBuf,
+    pub input_size: u64,
+    pub input_sha256: String,
+    pub rows_processed: usize,
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+impl Manifest {
+    pub fn new(
+        input_file: &Path,
+        rows_processed: usize,
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+    ) -> Result<Self> {
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(input_file)?, &mut hasher)?;
+
+        Ok(Self {
+            input_file: input_file.to_path_buf(),
+            input_size: fs::metadata(input_file)?.len(),
+            input_sha256: hex(&hasher.finalize()),
+            rows_processed,
+            version: crate::VERSION,
+            git_hash: crate::GIT_HASH,
+            started_at,
+            finished_at,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        serde_json::to_writer_pretty(File::create(path)?, self)?;
+
+        Ok(())
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
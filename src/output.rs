@@ -0,0 +1,1303 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{Read, Write};
+
+use anyhow::{Result, bail};
+use chrono::{DateTime, Utc};
+use comfy_table::{Cell, CellAlignment, Color, Table, presets};
+use csv::{ReaderBuilder, WriterBuilder};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use serde::ser::{SerializeStruct, Serializer};
+
+use transactions_processor::payment_engine::{
+    AccountStatus, Amount, JournalEntry, Transaction, TransactionType,
+};
+
+use crate::cli::AliasMissing;
+use crate::diff::AccountChange;
+
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    /// Emit `held` as `held_abs` and `held_negative` columns instead of a signed value.
+    pub split_sign: bool,
+    /// Emit the magnitude of `held` in the `held` column, so that `available + held` no longer
+    /// equals `total` for the accounts with disputed withdrawals.
+    pub held_magnitude: bool,
+    /// Emit the highest total the account reached as a `peak_total` column.
+    pub with_peak: bool,
+    /// Emit the gross deposited and withdrawn amounts as `gross_deposits` and `gross_withdrawals`
+    /// columns.
+    pub with_flows: bool,
+    /// Emit the gross deposits minus the gross withdrawals as a `net_movement` column.
+    pub with_net_movement: bool,
+    /// Emit the available funds minus the pending fees as an `available_net_of_pending` column.
+    pub with_net_available: bool,
+    /// Append a `TOTAL` row with the sums of the balances of all the accounts.
+    pub summary_row: bool,
+    /// Pad the client ids with leading zeros to this width.
+    pub client_width: Option<usize>,
+    /// External accounts written instead of the client ids.
+    pub client_aliases: Option<HashMap<u16, String>>,
+    /// Treatment of the clients missing in `client_aliases`.
+    pub alias_missing: AliasMissing,
+    /// Clients with any failed transaction. If set, a `had_errors` column is emitted.
+    pub failed_clients: Option<HashSet<u16>>,
+}
+
+impl OutputOptions {
+    /// Columns of the accounts output, in order.
+    fn columns(&self) -> Vec<Column> {
+        let mut columns = vec![Column::Client, Column::Available];
+        if self.split_sign {
+            columns.extend([Column::HeldAbs, Column::HeldNegative]);
+        } else if self.held_magnitude {
+            columns.push(Column::HeldMagnitude);
+        } else {
+            columns.push(Column::Held);
+        }
+        columns.extend([Column::Total, Column::Locked]);
+        if self.with_peak {
+            columns.push(Column::PeakTotal);
+        }
+        if self.with_flows {
+            columns.extend([Column::GrossDeposits, Column::GrossWithdrawals]);
+        }
+        if self.with_net_movement {
+            columns.push(Column::NetMovement);
+        }
+        if self.with_net_available {
+            columns.push(Column::NetAvailable);
+        }
+        if self.failed_clients.is_some() {
+            columns.push(Column::HadErrors);
+        }
+
+        columns
+    }
+
+    /// Columns of the accounts output. The `currency` column is included only if any of the
+    /// accounts is kept in a specific currency.
+    fn columns_for<'a>(
+        &self,
+        mut accounts: impl Iterator<Item = &'a AccountStatus>,
+    ) -> Vec<Column> {
+        let mut columns = self.columns();
+        if accounts.any(|account| account.currency.is_some()) {
+            columns.insert(1, Column::Currency);
+        }
+
+        columns
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Client,
+    Currency,
+    Available,
+    Held,
+    HeldAbs,
+    HeldNegative,
+    /// The magnitude of the held funds under the `held` name.
+    HeldMagnitude,
+    Total,
+    Locked,
+    PeakTotal,
+    GrossDeposits,
+    GrossWithdrawals,
+    NetMovement,
+    NetAvailable,
+    HadErrors,
+    DeltaAvailable,
+    DeltaHeld,
+    DeltaTotal,
+    Change,
+}
+
+impl Column {
+    fn name(self) -> &'static str {
+        match self {
+            Column::Client => "client",
+            Column::Currency => "currency",
+            Column::Available => "available",
+            Column::Held | Column::HeldMagnitude => "held",
+            Column::HeldAbs => "held_abs",
+            Column::HeldNegative => "held_negative",
+            Column::Total => "total",
+            Column::Locked => "locked",
+            Column::PeakTotal => "peak_total",
+            Column::GrossDeposits => "gross_deposits",
+            Column::GrossWithdrawals => "gross_withdrawals",
+            Column::NetMovement => "net_movement",
+            Column::NetAvailable => "available_net_of_pending",
+            Column::HadErrors => "had_errors",
+            Column::DeltaAvailable => "delta_available",
+            Column::DeltaHeld => "delta_held",
+            Column::DeltaTotal => "delta_total",
+            Column::Change => "change",
+        }
+    }
+}
+
+/// A single row of the accounts output, consisting of the given columns.
+struct AccountRow<'a> {
+    account: &'a AccountStatus,
+    columns: &'a [Column],
+    change: Option<AccountChange>,
+    /// The row sums all the accounts, so its `client` is `TOTAL` and the non-additive columns
+    /// are empty.
+    summary: bool,
+    client_width: Option<usize>,
+    /// External account written instead of the client id.
+    alias: Option<&'a str>,
+    had_errors: bool,
+}
+
+impl Serialize for AccountRow<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let account = self.account;
+
+        let mut row = serializer.serialize_struct("AccountStatus", self.columns.len())?;
+        for &column in self.columns {
+            let name = column.name();
+            match column {
+                Column::Client if self.summary => row.serialize_field(name, "TOTAL")?,
+                Column::Currency
+                | Column::Locked
+                | Column::PeakTotal
+                | Column::HadErrors
+                | Column::HeldNegative
+                    if self.summary =>
+                {
+                    row.serialize_field(name, &None::<()>)?
+                }
+                Column::Client => match (self.alias, self.client_width) {
+                    (Some(alias), _) => row.serialize_field(name, alias)?,
+                    (None, Some(width)) => {
+                        row.serialize_field(name, &format!("{:0width$}", account.client))?
+                    }
+                    (None, None) => row.serialize_field(name, &account.client)?,
+                },
+                Column::Currency => row.serialize_field(name, &account.currency)?,
+                Column::Available | Column::DeltaAvailable => {
+                    row.serialize_field(name, &account.available)?
+                }
+                Column::Held | Column::DeltaHeld => row.serialize_field(name, &account.held)?,
+                Column::HeldAbs | Column::HeldMagnitude => {
+                    row.serialize_field(name, &account.held.abs())?
+                }
+                Column::HeldNegative => {
+                    row.serialize_field(name, &account.held.is_sign_negative())?
+                }
+                Column::Total | Column::DeltaTotal => row.serialize_field(name, &account.total)?,
+                Column::Locked => row.serialize_field(name, &account.locked)?,
+                Column::PeakTotal => row.serialize_field(name, &account.peak_total)?,
+                Column::GrossDeposits => row.serialize_field(name, &account.gross_deposits)?,
+                Column::GrossWithdrawals => {
+                    row.serialize_field(name, &account.gross_withdrawals)?
+                }
+                Column::NetMovement => row.serialize_field(name, &account.net_movement())?,
+                Column::NetAvailable => row.serialize_field(name, &account.net_available())?,
+                Column::HadErrors => row.serialize_field(name, &self.had_errors)?,
+                Column::Change => row.serialize_field(name, &self.change)?,
+            }
+        }
+        row.end()
+    }
+}
+
+/// Writes the header row and the account rows, optionally followed by the summary row. The header
+/// is written even if there are no rows.
+fn write_rows<'a, W: Write>(
+    writer: W,
+    columns: &[Column],
+    rows: impl IntoIterator<Item = (&'a AccountStatus, Option<AccountChange>)>,
+    options: &OutputOptions,
+) -> Result<()> {
+    // resolved up front, so that a missing alias fails the output before anything is written
+    let rows = rows
+        .into_iter()
+        .map(|(account, change)| {
+            let alias = match &options.client_aliases {
+                Some(aliases) => match (aliases.get(&account.client), options.alias_missing) {
+                    (Some(alias), _) => Some(alias.as_str()),
+                    (None, AliasMissing::Passthrough) => None,
+                    (None, AliasMissing::Error) => {
+                        bail!("client {} has no alias", account.client)
+                    }
+                },
+                None => None,
+            };
+            Ok((account, change, alias))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(writer);
+    writer.write_record(columns.iter().map(|column| column.name()))?;
+    let mut summary = AccountStatus::new(0);
+    for (account, change, alias) in rows {
+        summary.available += account.available;
+        summary.held += if options.held_magnitude || options.split_sign {
+            account.held.abs()
+        } else {
+            account.held
+        };
+        summary.total += account.total;
+        summary.gross_deposits += account.gross_deposits;
+        summary.gross_withdrawals += account.gross_withdrawals;
+        summary.pending_fees += account.pending_fees;
+
+        writer.serialize(AccountRow {
+            account,
+            columns,
+            change,
+            summary: false,
+            client_width: options.client_width,
+            alias,
+            had_errors: options
+                .failed_clients
+                .as_ref()
+                .is_some_and(|failed_clients| failed_clients.contains(&account.client)),
+        })?;
+    }
+    if options.summary_row {
+        writer.serialize(AccountRow {
+            account: &summary,
+            columns,
+            change: None,
+            summary: true,
+            client_width: None,
+            alias: None,
+            had_errors: false,
+        })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+pub fn write_accounts<W: Write>(
+    writer: W,
+    accounts: &[AccountStatus],
+    options: &OutputOptions,
+) -> Result<()> {
+    write_rows(
+        writer,
+        &options.columns_for(accounts.iter()),
+        accounts.iter().map(|account| (account, None)),
+        options,
+    )
+}
+
+pub fn write_account_changes<W: Write>(
+    writer: W,
+    changes: &[(AccountStatus, AccountChange)],
+    options: &OutputOptions,
+) -> Result<()> {
+    let mut columns = options.columns_for(changes.iter().map(|(account, _)| account));
+    columns.push(Column::Change);
+
+    write_rows(
+        writer,
+        &columns,
+        changes
+            .iter()
+            .map(|(account, change)| (account, Some(*change))),
+        options,
+    )
+}
+
+/// Writes the changes of the balances computed by
+/// [`account_deltas`](crate::diff::account_deltas).
+pub fn write_account_deltas<W: Write>(
+    writer: W,
+    deltas: &[AccountStatus],
+    options: &OutputOptions,
+) -> Result<()> {
+    let mut columns = vec![
+        Column::Client,
+        Column::DeltaAvailable,
+        Column::DeltaHeld,
+        Column::DeltaTotal,
+    ];
+    if deltas.iter().any(|delta| delta.currency.is_some()) {
+        columns.insert(1, Column::Currency);
+    }
+
+    write_rows(
+        writer,
+        &columns,
+        deltas.iter().map(|delta| (delta, None)),
+        options,
+    )
+}
+
+/// Writes the `accounts` as a MessagePack array of maps with the same fields as the default CSV
+/// output. The balances are written as strings to preserve their precision.
+pub fn write_accounts_msgpack<W: Write>(mut writer: W, accounts: &[AccountStatus]) -> Result<()> {
+    rmp_serde::encode::write_named(&mut writer, accounts)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the `accounts` as an aligned text table. With `color`, the rows of the locked accounts
+/// are highlighted using ANSI escape codes.
+pub fn write_accounts_table<W: Write>(
+    mut writer: W,
+    accounts: &[AccountStatus],
+    color: bool,
+) -> Result<()> {
+    let with_currency = accounts.iter().any(|account| account.currency.is_some());
+    let mut header = vec!["client"];
+    if with_currency {
+        header.push("currency");
+    }
+    header.extend(["available", "held", "total", "locked"]);
+
+    let mut table = Table::new();
+    table.load_style(presets::ASCII_FULL_CONDENSED);
+    if color {
+        table.enforce_styling();
+    }
+    table.set_header(header.clone());
+    for account in accounts {
+        let mut row = vec![account.client.to_string()];
+        if with_currency {
+            row.push(account.currency.clone().unwrap_or_default());
+        }
+        row.extend([
+            account.available.to_string(),
+            account.held.to_string(),
+            account.total.to_string(),
+            account.locked.to_string(),
+        ]);
+
+        let cells = row.into_iter().map(|value| {
+            let cell = Cell::new(value);
+            if color && account.locked {
+                cell.fg(Color::Red)
+            } else {
+                cell
+            }
+        });
+        table.add_row(cells);
+    }
+    // the balances are aligned on the right to make their magnitudes comparable
+    for (column, name) in table.column_iter_mut().zip(header) {
+        if matches!(name, "available" | "held" | "total") {
+            column.set_cell_alignment(CellAlignment::Right);
+        }
+    }
+    writeln!(writer, "{table}")?;
+
+    Ok(())
+}
+
+/// Reads accounts previously written by [`write_accounts`] with the default options.
+pub fn read_accounts<R: Read>(reader: R) -> Result<Vec<AccountStatus>> {
+    let mut reader = ReaderBuilder::new().from_reader(reader);
+    let accounts = reader.deserialize().collect::<Result<_, _>>()?;
+
+    Ok(accounts)
+}
+
+#[derive(Serialize)]
+struct OrphanDisputeRow {
+    r#type: &'static str,
+    client: u16,
+    tx: u32,
+}
+
+pub fn write_orphan_disputes<W: Write>(writer: W, disputes: &[Transaction]) -> Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for dispute in disputes {
+        writer.serialize(OrphanDisputeRow {
+            r#type: dispute.r#type.name(),
+            client: dispute.client,
+            tx: dispute.id,
+        })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct LifecycleViolationRow {
+    r#type: &'static str,
+    client: u16,
+    tx: u32,
+    violation: &'static str,
+}
+
+/// Writes the dispute operations which violate the dispute lifecycle of the referenced
+/// transaction. The header is written even if there are no violations.
+pub fn write_lifecycle_violations<W: Write>(writer: W, operations: &[Transaction]) -> Result<()> {
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(writer);
+    writer.write_record(["type", "client", "tx", "violation"])?;
+    for operation in operations {
+        let violation = match operation.r#type {
+            TransactionType::Dispute => "already_disputed",
+            _ => "not_disputed",
+        };
+        writer.serialize(LifecycleViolationRow {
+            r#type: operation.r#type.name(),
+            client: operation.client,
+            tx: operation.id,
+            violation,
+        })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the journal of an account as a statement. The header is written even if the journal is
+/// empty.
+pub fn write_journal<W: Write>(writer: W, journal: &[JournalEntry]) -> Result<()> {
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(writer);
+    writer.write_record([
+        "tx",
+        "type",
+        "amount",
+        "available_after",
+        "held_after",
+        "total_after",
+    ])?;
+    for entry in journal {
+        writer.serialize(entry)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct OpenDisputeRow {
+    tx: u32,
+    client: u16,
+    amount: Option<Amount>,
+}
+
+/// Writes a row for every transaction which is still under dispute. The header is written even if
+/// there are no such transactions.
+pub fn write_open_disputes<W: Write>(writer: W, disputes: &[Cow<'_, Transaction>]) -> Result<()> {
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(writer);
+    writer.write_record(["tx", "client", "amount"])?;
+    for dispute in disputes {
+        writer.serialize(OpenDisputeRow {
+            tx: dispute.id,
+            client: dispute.client,
+            amount: dispute.r#type.amount(),
+        })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Replaces the client ids of the `accounts` with a contiguous sequence starting at 1, assigned in
+/// the order of the original ids. The accounts are sorted by the client and the `(original, new)`
+/// pairs of ids are returned.
+pub fn normalize_clients(accounts: &mut [AccountStatus]) -> Vec<(u16, u16)> {
+    accounts.sort_by(|a, b| (a.client, &a.currency).cmp(&(b.client, &b.currency)));
+
+    let mut mapping: Vec<(u16, u16)> = Vec::new();
+    for account in accounts {
+        let new_client = match mapping.last() {
+            // the accounts of a client in different currencies are adjacent
+            Some(&(original, new)) if original == account.client => new,
+            last => {
+                let new = last.map_or(1, |&(_, new)| new + 1);
+                mapping.push((account.client, new));
+                new
+            }
+        };
+        account.client = new_client;
+    }
+
+    mapping
+}
+
+/// Replaces the client ids of the `accounts` by the new ones of the `mapping` returned by
+/// [`normalize_clients`]. Clients missing from the `mapping` are left as they are.
+pub fn remap_clients(accounts: &mut [AccountStatus], mapping: &[(u16, u16)]) {
+    let mapping = mapping.iter().copied().collect::<HashMap<_, _>>();
+    for account in accounts {
+        if let Some(&new_client) = mapping.get(&account.client) {
+            account.client = new_client;
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ClientMapRow {
+    original: u16,
+    new: u16,
+}
+
+pub fn write_client_map<W: Write>(writer: W, mapping: &[(u16, u16)]) -> Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for &(original, new) in mapping {
+        writer.serialize(ClientMapRow { original, new })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct StatusRow {
+    client: u16,
+    locked: bool,
+}
+
+/// Writes only the `client` and `locked` columns, sorted by client. A client with accounts in
+/// several currencies is written once and is locked if any of its accounts is.
+pub fn write_account_statuses<W: Write>(writer: W, accounts: &[AccountStatus]) -> Result<()> {
+    let mut statuses = BTreeMap::new();
+    for account in accounts {
+        *statuses.entry(account.client).or_default() |= account.locked;
+    }
+
+    let mut writer = WriterBuilder::new().has_headers(false).from_writer(writer);
+    writer.write_record(["client", "locked"])?;
+    for (client, locked) in statuses {
+        writer.serialize(StatusRow { client, locked })?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the balances of every account rounded (half to even) to each of the `scales`, e.g.
+/// `client,available_2,held_2,total_2,available_4,held_4,total_4,total_difference`, to quantify
+/// the rounding error. The `total_difference` is the total at the smallest scale minus the total
+/// at the largest one.
+pub fn write_scale_comparison<W: Write>(
+    writer: W,
+    accounts: &[AccountStatus],
+    scales: &BTreeSet<u32>,
+) -> Result<()> {
+    let with_currency = accounts.iter().any(|account| account.currency.is_some());
+    let rescale = |balance: Decimal, scale: u32| {
+        let mut balance = balance.round_dp(scale);
+        balance.rescale(scale);
+        balance
+    };
+
+    let mut header = vec!["client".to_string()];
+    if with_currency {
+        header.push("currency".to_string());
+    }
+    for scale in scales {
+        header.extend(["available", "held", "total"].map(|column| format!("{column}_{scale}")));
+    }
+    header.push("total_difference".to_string());
+
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    writer.write_record(&header)?;
+    for account in accounts {
+        let mut record = vec![account.client.to_string()];
+        if with_currency {
+            record.push(account.currency.clone().unwrap_or_default());
+        }
+        for &scale in scales {
+            for balance in [account.available, account.held, account.total] {
+                record.push(rescale(balance, scale).to_string());
+            }
+        }
+        let difference = match (scales.first(), scales.last()) {
+            (Some(&smallest), Some(&largest)) => {
+                rescale(account.total, smallest) - rescale(account.total, largest)
+            }
+            _ => Decimal::ZERO,
+        };
+        record.push(difference.to_string());
+        writer.write_record(&record)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct LockRow {
+    client: u16,
+    locked_by_tx: u32,
+    timestamp: DateTime<Utc>,
+}
+
+/// Writes a row for every account which was locked during the processing.
+pub fn write_locks<W: Write>(writer: W, accounts: &[AccountStatus]) -> Result<()> {
+    let mut writer = WriterBuilder::new().from_writer(writer);
+    for account in accounts {
+        if let (Some(locked_by_tx), Some(timestamp)) = (account.locked_by, account.locked_at) {
+            writer.serialize(LockRow {
+                client: account.client,
+                locked_by_tx,
+                timestamp,
+            })?;
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rust_decimal::{Decimal, dec};
+
+    use super::*;
+    use crate::test_utils::amount;
+    use transactions_processor::payment_engine::{PaymentEngine, Transaction, TransactionType};
+
+    #[test]
+    fn test_split_sign_disputed_withdrawal() {
+        let mut engine = PaymentEngine::new();
+        engine
+            .process_transaction(Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(100.0)),
+                },
+            ))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(40.0)),
+                },
+            ))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::new(1, 2, TransactionType::Dispute))
+            .unwrap();
+
+        let options = OutputOptions {
+            split_sign: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        write_accounts(&mut output, &engine.get_accounts_statuses(), &options).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "client,available,held_abs,held_negative,total,locked\n\
+             1,100.0,40.0,true,60.0,false\n"
+        );
+    }
+
+    #[test]
+    fn test_held_magnitude() {
+        let mut engine = PaymentEngine::new();
+        for transaction in [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(100.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(40.0)),
+                },
+            ),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            Transaction::new(
+                2,
+                3,
+                TransactionType::Deposit {
+                    amount: amount(dec!(5.0)),
+                },
+            ),
+            Transaction::new(2, 3, TransactionType::Dispute),
+        ] {
+            engine.process_transaction(transaction).unwrap();
+        }
+        let mut accounts = engine.get_accounts_statuses();
+        accounts.sort_by_key(|a| a.client);
+
+        let options = OutputOptions {
+            held_magnitude: true,
+            summary_row: true,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        write_accounts(&mut output, &accounts, &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n\
+             1,100.0,40.0,60.0,false\n\
+             2,0.0,5.0,5.0,false\n\
+             TOTAL,100.0,45.0,65.0,\n"
+        );
+    }
+
+    #[test]
+    fn test_client_aliases() {
+        let accounts = [AccountStatus::new(1), AccountStatus::new(2)];
+        let mut options = OutputOptions {
+            client_aliases: Some(HashMap::from([(1, "ACC-0001".to_string())])),
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        write_accounts(&mut output, &accounts[..1], &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n\
+             ACC-0001,0,0,0,false\n"
+        );
+
+        let mut output = Vec::new();
+        let err = write_accounts(&mut output, &accounts, &options).unwrap_err();
+        assert_eq!(err.to_string(), "client 2 has no alias");
+        assert!(output.is_empty());
+
+        options.alias_missing = AliasMissing::Passthrough;
+        let mut output = Vec::new();
+        write_accounts(&mut output, &accounts, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n\
+             ACC-0001,0,0,0,false\n\
+             2,0,0,0,false\n"
+        );
+    }
+
+    #[test]
+    fn test_write_no_accounts() {
+        let mut output = Vec::new();
+        write_accounts(&mut output, &[], &OutputOptions::default()).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "client,available,held,total,locked\n");
+    }
+
+    #[test]
+    fn test_currency_column() {
+        let mut engine = PaymentEngine::new();
+        for (id, currency) in [(1, "USD"), (2, "EUR")] {
+            engine
+                .process_transaction(
+                    Transaction::new(
+                        1,
+                        id,
+                        TransactionType::Deposit {
+                            amount: amount(dec!(10.0)),
+                        },
+                    )
+                    .with_currency(Some(currency.to_string())),
+                )
+                .unwrap();
+        }
+
+        let mut accounts = engine.get_accounts_statuses();
+        accounts.sort_by(|a, b| a.currency.cmp(&b.currency));
+        let mut output = Vec::new();
+        write_accounts(&mut output, &accounts, &OutputOptions::default()).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "client,currency,available,held,total,locked\n\
+             1,EUR,10.0,0,10.0,false\n\
+             1,USD,10.0,0,10.0,false\n"
+        );
+    }
+
+    #[test]
+    fn test_with_flows() {
+        let mut engine = PaymentEngine::new();
+        for transaction in [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(5.5)),
+                },
+            ),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(3.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                4,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(1.5)),
+                },
+            ),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            Transaction::new(1, 2, TransactionType::Chargeback),
+        ] {
+            engine.process_transaction(transaction).unwrap();
+        }
+        // rejected withdrawal is not counted
+        engine
+            .process_transaction(Transaction::new(
+                1,
+                5,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(100.0)),
+                },
+            ))
+            .unwrap_err();
+
+        let mut output = Vec::new();
+        let options = OutputOptions {
+            with_flows: true,
+            ..Default::default()
+        };
+        write_accounts(&mut output, &engine.get_accounts_statuses(), &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked,gross_deposits,gross_withdrawals\n\
+             1,5.5,0.0,5.5,true,15.5,4.5\n"
+        );
+    }
+
+    #[test]
+    fn test_with_net_movement() {
+        let (engine, _) = PaymentEngine::from_records([
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(2.5)),
+                },
+            ),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Deposit {
+                    amount: amount(dec!(4.25)),
+                },
+            ),
+            Transaction::new(
+                1,
+                4,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(1.0)),
+                },
+            ),
+            Transaction::new(1, 3, TransactionType::Dispute),
+            Transaction::new(
+                2,
+                5,
+                TransactionType::Deposit {
+                    amount: amount(dec!(1.0)),
+                },
+            ),
+            Transaction::new(
+                2,
+                6,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(1.0)),
+                },
+            ),
+        ]);
+        let mut accounts = engine.get_accounts_statuses();
+        accounts.sort_by_key(|a| a.client);
+
+        let mut output = Vec::new();
+        let options = OutputOptions {
+            with_net_movement: true,
+            ..Default::default()
+        };
+        write_accounts(&mut output, &accounts, &options).unwrap();
+
+        // the dispute does not change the net movement
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked,net_movement\n\
+             1,6.50,4.25,10.75,false,10.75\n\
+             2,0.0,0,0.0,false,0.0\n"
+        );
+    }
+
+    #[test]
+    fn test_with_net_available() {
+        let (engine, _) = PaymentEngine::from_records([
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::PendingFee {
+                    amount: amount(dec!(1.5)),
+                },
+            ),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::PendingFee {
+                    amount: amount(dec!(0.25)),
+                },
+            ),
+        ]);
+        let accounts = engine.get_accounts_statuses();
+
+        let mut output = Vec::new();
+        let options = OutputOptions {
+            with_net_available: true,
+            ..Default::default()
+        };
+        write_accounts(&mut output, &accounts, &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked,available_net_of_pending\n\
+             1,10.0,0,10.0,false,8.25\n"
+        );
+    }
+
+    #[test]
+    fn test_summary_row() {
+        let (engine, _) = PaymentEngine::from_records([
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            ),
+            Transaction::new(
+                2,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(5.25)),
+                },
+            ),
+            Transaction::new(
+                2,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(1.0)),
+                },
+            ),
+            Transaction::new(
+                3,
+                4,
+                TransactionType::Deposit {
+                    amount: amount(dec!(2.5)),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+        ]);
+        let mut accounts = engine.get_accounts_statuses();
+        accounts.sort_by_key(|a| a.client);
+
+        let mut output = Vec::new();
+        let options = OutputOptions {
+            summary_row: true,
+            ..Default::default()
+        };
+        write_accounts(&mut output, &accounts, &options).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let rows = output
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let (summary, data) = rows.split_last().unwrap();
+        assert_eq!(summary[0], "TOTAL");
+        assert_eq!(summary[4], "");
+        for column in 1..=3 {
+            let sum = data
+                .iter()
+                .map(|row| Decimal::from_str(row[column]).unwrap())
+                .sum::<Decimal>();
+            assert_eq!(Decimal::from_str(summary[column]).unwrap(), sum);
+        }
+        assert_eq!(summary[1..4], ["6.75", "10.0", "16.75"]);
+
+        // the magnitudes of the held funds are summed up, their sign is not additive
+        let (engine, _) = PaymentEngine::from_records([
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(40.0)),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(
+                2,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            ),
+            Transaction::new(
+                2,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(5.0)),
+                },
+            ),
+            Transaction::new(2, 3, TransactionType::Dispute),
+        ]);
+        let mut accounts = engine.get_accounts_statuses();
+        accounts.sort_by_key(|a| a.client);
+
+        let mut output = Vec::new();
+        let options = OutputOptions {
+            summary_row: true,
+            split_sign: true,
+            ..Default::default()
+        };
+        write_accounts(&mut output, &accounts, &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held_abs,held_negative,total,locked\n\
+             1,0.0,40.0,false,40.0,false\n\
+             2,10.0,5.0,true,5.0,false\n\
+             TOTAL,10.0,45.0,,45.0,\n"
+        );
+    }
+
+    #[test]
+    fn test_client_width() {
+        let (engine, _) = PaymentEngine::from_records([Transaction::new(
+            42,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(1.5)),
+            },
+        )]);
+
+        let mut output = Vec::new();
+        let options = OutputOptions {
+            client_width: Some(5),
+            ..Default::default()
+        };
+        write_accounts(&mut output, &engine.get_accounts_statuses(), &options).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked\n00042,1.5,0,1.5,false\n"
+        );
+    }
+
+    #[test]
+    fn test_write_accounts_msgpack() {
+        let (engine, _) = PaymentEngine::from_records([
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(12.3400)),
+                },
+            ),
+            Transaction::new(
+                2,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(5.0)),
+                },
+            )
+            .with_currency(Some("EUR".to_string())),
+            Transaction::new(2, 2, TransactionType::Dispute).with_currency(Some("EUR".to_string())),
+            Transaction::new(2, 2, TransactionType::Chargeback)
+                .with_currency(Some("EUR".to_string())),
+        ]);
+        let mut accounts = engine.get_accounts_statuses();
+        accounts.sort_by_key(|a| a.client);
+
+        let mut output = Vec::new();
+        write_accounts_msgpack(&mut output, &accounts).unwrap();
+
+        let decoded: Vec<AccountStatus> = rmp_serde::from_slice(&output).unwrap();
+        let fields = |accounts: &[AccountStatus]| {
+            accounts
+                .iter()
+                .map(|a| {
+                    (
+                        a.client,
+                        a.currency.clone(),
+                        a.available,
+                        a.held,
+                        a.total,
+                        a.locked,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(fields(&decoded), fields(&accounts));
+
+        // the balances are strings keeping the scale of the amounts
+        let values: Vec<serde_json::Value> = rmp_serde::from_slice(&output).unwrap();
+        assert_eq!(
+            values[0],
+            serde_json::json!({
+                "client": 1,
+                "available": "12.3400",
+                "held": "0",
+                "total": "12.3400",
+                "locked": false,
+            })
+        );
+        assert_eq!(values[1]["currency"], "EUR");
+    }
+
+    #[test]
+    fn test_write_accounts_table() {
+        let (engine, _) = PaymentEngine::from_records([
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(1234.5)),
+                },
+            ),
+            Transaction::new(
+                22,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(7.25)),
+                },
+            ),
+            Transaction::new(22, 2, TransactionType::Dispute),
+            Transaction::new(22, 2, TransactionType::Chargeback),
+        ]);
+        let mut accounts = engine.get_accounts_statuses();
+        accounts.sort_by_key(|a| a.client);
+
+        let mut output = Vec::new();
+        write_accounts_table(&mut output, &accounts, false).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
++--------+-----------+------+--------+--------+
+| client | available | held |  total | locked |
++=============================================+
+| 1      |    1234.5 |    0 | 1234.5 | false  |
+| 22     |      0.00 | 0.00 |   0.00 | true   |
++--------+-----------+------+--------+--------+
+"
+        );
+
+        let mut output = Vec::new();
+        write_accounts_table(&mut output, &accounts, true).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert!(!lines[3].contains('\x1b'));
+        assert!(lines[4].contains('\x1b'));
+    }
+
+    #[test]
+    fn test_write_account_statuses() {
+        let (engine, _) = PaymentEngine::from_records([
+            Transaction::new(
+                3,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(1.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(7.25)),
+                },
+            ),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            Transaction::new(1, 2, TransactionType::Chargeback),
+            Transaction::new(
+                2,
+                3,
+                TransactionType::Deposit {
+                    amount: amount(dec!(2.0)),
+                },
+            ),
+        ]);
+
+        let mut output = Vec::new();
+        write_account_statuses(&mut output, &engine.get_accounts_statuses()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,locked\n\
+             1,true\n\
+             2,false\n\
+             3,false\n"
+        );
+    }
+
+    #[test]
+    fn test_write_scale_comparison() {
+        let mut engine = PaymentEngine::new();
+        for (client, tx, value) in [
+            (1, 1, dec!(1.2345)),
+            (1, 2, dec!(0.0051)),
+            (2, 3, dec!(3.0)),
+        ] {
+            engine
+                .process_transaction(Transaction::new(
+                    client,
+                    tx,
+                    TransactionType::Deposit {
+                        amount: amount(value),
+                    },
+                ))
+                .unwrap();
+        }
+        engine
+            .process_transaction(Transaction::new(1, 2, TransactionType::Dispute))
+            .unwrap();
+        let mut accounts = engine.get_accounts_statuses();
+        accounts.sort_by_key(|account| account.client);
+
+        let mut output = Vec::new();
+        write_scale_comparison(&mut output, &accounts, &BTreeSet::from([4, 2])).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available_2,held_2,total_2,available_4,held_4,total_4,total_difference\n\
+             1,1.23,0.01,1.24,1.2345,0.0051,1.2396,0.0004\n\
+             2,3.00,0.00,3.00,3.0000,0.0000,3.0000,0.0000\n"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::Result;
+use arrow_array::{ArrayRef, BooleanArray, Decimal128Array, RecordBatch, StringArray, UInt16Array};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use rust_decimal::Decimal;
+
+use transactions_processor::payment_engine::{AccountStatus, Amount};
+
+/// Precision of the decimal columns, the maximum supported by `Decimal128`.
+const PRECISION: u8 = 38;
+/// Scale of the decimal columns. The balances are sums of the transaction amounts, so they never
+/// need more decimal places than the amounts.
+const SCALE: u32 = Amount::MAX_SCALE;
+
+/// Writes the `accounts` as a Parquet file with the `client`, `currency`, `available`, `held`,
+/// `total` and `locked` columns. Unlike the CSV output, the `currency` column is always present.
+pub fn write_accounts_parquet<W: Write + Send>(
+    writer: W,
+    accounts: &[AccountStatus],
+) -> Result<()> {
+    let decimal_type = DataType::Decimal128(PRECISION, SCALE as i8);
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("client", DataType::UInt16, false),
+        Field::new("currency", DataType::Utf8, true),
+        Field::new("available", decimal_type.clone(), false),
+        Field::new("held", decimal_type.clone(), false),
+        Field::new("total", decimal_type, false),
+        Field::new("locked", DataType::Boolean, false),
+    ]));
+
+    let decimals = |balance: fn(&AccountStatus) -> Decimal| -> Result<ArrayRef> {
+        let array = Decimal128Array::from_iter_values(
+            accounts.iter().map(|account| to_mantissa(balance(account))),
+        )
+        .with_precision_and_scale(PRECISION, SCALE as i8)?;
+        Ok(Arc::new(array))
+    };
+    let batch = RecordBatch::try_new(
+        schema.clone(),
+        vec![
+            Arc::new(UInt16Array::from_iter_values(
+                accounts.iter().map(|account| account.client),
+            )),
+            Arc::new(StringArray::from_iter(
+                accounts.iter().map(|account| account.currency.as_deref()),
+            )),
+            decimals(|account| account.available)?,
+            decimals(|account| account.held)?,
+            decimals(|account| account.total)?,
+            Arc::new(BooleanArray::from_iter(
+                accounts.iter().map(|account| Some(account.locked)),
+            )),
+        ],
+    )?;
+
+    let mut writer = ArrowWriter::try_new(writer, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    Ok(())
+}
+
+/// Unscaled value of the `balance` at [`SCALE`], rounding any excess decimal places.
+fn to_mantissa(balance: Decimal) -> i128 {
+    let mut balance = balance.round_dp(SCALE);
+    balance.rescale(SCALE);
+    balance.mantissa()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::{env, process};
+
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use rust_decimal::dec;
+
+    use super::*;
+
+    #[test]
+    fn test_write_accounts_parquet() {
+        let accounts = [
+            AccountStatus {
+                available: dec!(1.5),
+                total: dec!(1.5),
+                ..AccountStatus::new(1)
+            },
+            AccountStatus {
+                currency: Some("EUR".to_string()),
+                available: dec!(-2.25),
+                held: dec!(10.1234),
+                total: dec!(7.8734),
+                locked: true,
+                ..AccountStatus::new(2)
+            },
+        ];
+        let path =
+            env::temp_dir().join(format!("transactions-processor-{}.parquet", process::id()));
+
+        write_accounts_parquet(File::create(&path).unwrap(), &accounts).unwrap();
+
+        let batches = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+
+        let column = |name| batch.column_by_name(name).unwrap();
+        let clients = column("client")
+            .as_any()
+            .downcast_ref::<UInt16Array>()
+            .unwrap();
+        assert_eq!(clients.values(), &[1, 2]);
+        let currencies = column("currency")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert!(currencies.is_null(0));
+        assert_eq!(currencies.value(1), "EUR");
+        for (name, expected) in [
+            ("available", ["1.5000", "-2.2500"]),
+            ("held", ["0.0000", "10.1234"]),
+            ("total", ["1.5000", "7.8734"]),
+        ] {
+            let values = column(name)
+                .as_any()
+                .downcast_ref::<Decimal128Array>()
+                .unwrap();
+            assert_eq!(values.scale(), 4);
+            assert_eq!(
+                [values.value_as_string(0), values.value_as_string(1)],
+                expected
+            );
+        }
+        let locked = column("locked")
+            .as_any()
+            .downcast_ref::<BooleanArray>()
+            .unwrap();
+        assert!(!locked.value(0));
+        assert!(locked.value(1));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,69 +1,253 @@
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::payment_engine::PaymentEngineError;
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AccountStatus {
     pub client: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
     pub available: Decimal,
     pub held: Decimal,
     pub total: Decimal,
     pub locked: bool,
+    #[serde(skip)]
+    pub has_deposited: bool,
+    /// Whether the account was explicitly opened by an `OpenAccount` transaction.
+    #[serde(skip)]
+    pub is_open: bool,
+    /// Whether withdrawals were frozen by a `FreezeWithdrawals` transaction. Unlike `locked`, this
+    /// does not affect deposits or disputes.
+    #[serde(skip)]
+    pub withdrawals_frozen: bool,
+    /// Highest `total` the account reached during processing.
+    #[serde(skip)]
+    pub peak_total: Decimal,
+    /// Sum of all the accepted deposits, regardless of later disputes.
+    #[serde(skip)]
+    pub gross_deposits: Decimal,
+    /// Sum of all the accepted withdrawals, regardless of later disputes.
+    #[serde(skip)]
+    pub gross_withdrawals: Decimal,
+    /// Sum of the fees which are known, but not charged yet.
+    #[serde(skip)]
+    pub pending_fees: Decimal,
+    /// Transaction whose chargeback locked the account.
+    #[serde(skip)]
+    pub locked_by: Option<u32>,
+    /// Time at which the account was locked, if the engine has a `clock`.
+    #[serde(skip)]
+    pub locked_at: Option<DateTime<Utc>>,
+    /// Operations applied to the account in the order of processing. Recorded only with
+    /// `journal` enabled in the config.
+    #[serde(skip)]
+    pub journal: Vec<JournalEntry>,
+}
+
+/// Operation applied to an account together with the resulting balances.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub tx: u32,
+    #[serde(rename = "type")]
+    pub r#type: &'static str,
+    /// Amount of the transaction, or of the referenced transaction for dispute operations and
+    /// settles.
+    pub amount: Option<Decimal>,
+    pub available_after: Decimal,
+    pub held_after: Decimal,
+    pub total_after: Decimal,
 }
 
 impl AccountStatus {
     pub fn new(client_id: u16) -> Self {
         Self {
             client: client_id,
+            currency: None,
             available: Decimal::ZERO,
             held: Decimal::ZERO,
             total: Decimal::ZERO,
             locked: false,
+            has_deposited: false,
+            is_open: false,
+            withdrawals_frozen: false,
+            peak_total: Decimal::ZERO,
+            gross_deposits: Decimal::ZERO,
+            gross_withdrawals: Decimal::ZERO,
+            pending_fees: Decimal::ZERO,
+            locked_by: None,
+            locked_at: None,
+            journal: Vec::new(),
         }
     }
 
+    /// Net amount moved into the account, i.e. the gross deposits minus the gross withdrawals,
+    /// regardless of later disputes.
+    pub fn net_movement(&self) -> Decimal {
+        self.gross_deposits - self.gross_withdrawals
+    }
+
+    /// Available funds which remain once the pending fees are charged.
+    pub fn net_available(&self) -> Decimal {
+        self.available - self.pending_fees
+    }
+
+    /// Appends an operation to the journal of the account with the current balances.
+    pub fn record(&mut self, transaction_id: u32, r#type: &'static str, amount: Option<Decimal>) {
+        self.journal.push(JournalEntry {
+            tx: transaction_id,
+            r#type,
+            amount,
+            available_after: self.available,
+            held_after: self.held,
+            total_after: self.total,
+        });
+    }
+
+    /// Sets all the balances computed by an operation at once, together with the `peak_total`
+    /// derived from them, so that e.g. a hook never observes a `total` exceeding the
+    /// `peak_total`. The operations validate the new balances before committing them, so a
+    /// rejected operation leaves the account unchanged.
+    fn commit(&mut self, available: Decimal, held: Decimal, total: Decimal) {
+        self.available = available;
+        self.held = held;
+        self.total = total;
+        self.peak_total = self.peak_total.max(total);
+    }
+
     pub fn deposit(&mut self, amount: Decimal) -> Result<(), PaymentEngineError> {
         if self.locked {
             return Err(PaymentEngineError::AccountLocked(self.client));
         }
 
-        self.available += amount;
-        self.total += amount;
+        self.commit(self.available + amount, self.held, self.total + amount);
+        self.has_deposited = true;
 
         Ok(())
     }
 
-    pub fn withdraw(&mut self, amount: Decimal) -> Result<(), PaymentEngineError> {
+    /// Withdraws `amount` from the available funds. The withdrawal may exceed the available
+    /// funds by at most `epsilon`, in which case the available funds are clamped to zero. With
+    /// `no_negative_total`, a withdrawal which would make the total funds negative (possible while
+    /// a withdrawal is disputed) is rejected. Returns the amount actually withdrawn.
+    pub fn withdraw(
+        &mut self,
+        amount: Decimal,
+        epsilon: Decimal,
+        no_negative_total: bool,
+    ) -> Result<Decimal, PaymentEngineError> {
         if self.locked {
             return Err(PaymentEngineError::AccountLocked(self.client));
         }
+        if self.withdrawals_frozen {
+            return Err(PaymentEngineError::WithdrawalsFrozen(self.client));
+        }
 
-        if self.available < amount {
+        let shortfall = (amount - self.available).max(Decimal::ZERO);
+        if shortfall > epsilon {
             return Err(PaymentEngineError::InsufficientFunds);
         }
 
-        self.available -= amount;
-        self.total -= amount;
+        let withdrawn = amount - shortfall;
+        if no_negative_total && self.total - withdrawn < Decimal::ZERO {
+            return Err(PaymentEngineError::NegativeTotal(self.client));
+        }
+
+        self.commit(
+            self.available - withdrawn,
+            self.held,
+            self.total - withdrawn,
+        );
+
+        Ok(withdrawn)
+    }
+
+    pub fn pending_deposit(&mut self, amount: Decimal) -> Result<(), PaymentEngineError> {
+        if self.locked {
+            return Err(PaymentEngineError::AccountLocked(self.client));
+        }
+
+        self.commit(self.available, self.held + amount, self.total + amount);
 
         Ok(())
     }
 
+    pub fn settle(&mut self, amount: Decimal) {
+        self.commit(self.available + amount, self.held - amount, self.total);
+        self.has_deposited = true;
+    }
+
+    /// Moves `amount` from the available to the held funds. The `amount` of a disputed withdrawal
+    /// is negative, as the dispute provisionally returns the withdrawn funds.
     pub fn hold_funds(&mut self, amount: Decimal) -> Result<(), PaymentEngineError> {
-        self.available -= amount;
-        self.held += amount;
+        self.commit(self.available - amount, self.held + amount, self.total);
+
+        Ok(())
+    }
+
+    /// Releases previously held funds, reverting [`hold_funds`](Self::hold_funds) with the same
+    /// `amount`. For disputed withdrawals the `amount` is negative, so releasing it moves the
+    /// withdrawn funds out of `available` again. With `guard` enabled, releases which would make
+    /// the held funds negative are rejected.
+    pub fn release_funds(
+        &mut self,
+        amount: Decimal,
+        guard: bool,
+    ) -> Result<(), PaymentEngineError> {
+        let held = self.held - amount;
+        let available = self.available + amount;
+        if guard && held < Decimal::ZERO {
+            return Err(PaymentEngineError::ReleaseExceedsHeld(self.client));
+        }
+
+        self.commit(available, held, self.total);
 
         Ok(())
     }
 
-    pub fn release_funds(&mut self, amount: Decimal) {
-        self.held -= amount;
-        self.available += amount;
+    /// Zeroes out held funds whose magnitude is below `epsilon`, adjusting the total accordingly.
+    /// With `no_negative_total`, a residue whose removal would make the total funds negative is
+    /// kept. Returns the removed residue, if any.
+    pub fn clamp_held_residue(
+        &mut self,
+        epsilon: Decimal,
+        no_negative_total: bool,
+    ) -> Option<Decimal> {
+        let residue = self.held;
+        if residue.is_zero() || residue.abs() >= epsilon {
+            return None;
+        }
+        if no_negative_total && self.total - residue < Decimal::ZERO {
+            return None;
+        }
+
+        self.commit(self.available, Decimal::ZERO, self.total - residue);
+
+        Some(residue)
     }
 
-    pub fn chargeback(&mut self, amount: Decimal) {
-        self.held -= amount;
-        self.total -= amount;
-        self.locked = true;
+    /// Withdraws the held `amount` and, if `lock` is set, locks the account at `now`. With
+    /// `no_negative_total`, a chargeback which would make the total funds negative is rejected.
+    pub fn chargeback(
+        &mut self,
+        amount: Decimal,
+        transaction_id: u32,
+        lock: bool,
+        no_negative_total: bool,
+        now: Option<DateTime<Utc>>,
+    ) -> Result<(), PaymentEngineError> {
+        if no_negative_total && self.total - amount < Decimal::ZERO {
+            return Err(PaymentEngineError::NegativeTotal(self.client));
+        }
+
+        self.commit(self.available, self.held - amount, self.total - amount);
+        if lock && !self.locked {
+            self.locked = true;
+            self.locked_by = Some(transaction_id);
+            self.locked_at = now;
+        }
+
+        Ok(())
     }
 }
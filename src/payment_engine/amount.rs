@@ -0,0 +1,80 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::payment_engine::PaymentEngineError;
+
+/// Amount of a deposit or a withdrawal. It is guaranteed to be non-negative and to have a limited
+/// number of decimal places.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(transparent)]
+pub struct Amount(Decimal);
+
+impl Amount {
+    /// Maximum number of decimal places of the amounts read from the input.
+    pub const MAX_SCALE: u32 = 4;
+
+    /// Validates the `value`, which may have at most `max_scale` significant decimal places.
+    pub fn try_new(value: Decimal, max_scale: u32) -> Result<Self, PaymentEngineError> {
+        if value < Decimal::ZERO {
+            return Err(PaymentEngineError::InvalidAmount(
+                value,
+                "amount cannot be negative".to_string(),
+            ));
+        }
+
+        if value.normalize().scale() > max_scale {
+            return Err(PaymentEngineError::InvalidAmount(
+                value,
+                format!("amount cannot have more than {max_scale} decimal places"),
+            ));
+        }
+
+        Ok(Self(value))
+    }
+
+    /// Wraps a `value` which was already validated by [`Amount::try_new`], e.g. when reading back
+    /// a stored transaction.
+    pub(super) fn new_unchecked(value: Decimal) -> Self {
+        Self(value)
+    }
+
+    pub fn value(self) -> Decimal {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use super::*;
+
+    #[test]
+    fn test_valid_amount() {
+        let amount = Amount::try_new(dec!(42.1234), 4).unwrap();
+        assert_eq!(amount.value(), dec!(42.1234));
+        assert_eq!(Amount::try_new(dec!(0), 4).unwrap().value(), dec!(0));
+    }
+
+    #[test]
+    fn test_negative_amount() {
+        assert!(matches!(
+            Amount::try_new(dec!(-10.0), 4),
+            Err(PaymentEngineError::InvalidAmount(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_amount_scale() {
+        assert!(matches!(
+            Amount::try_new(dec!(1.00001), 4),
+            Err(PaymentEngineError::InvalidAmount(_, _))
+        ));
+        assert!(Amount::try_new(dec!(1.00001), 5).is_ok());
+        // trailing zeros are not significant
+        assert_eq!(
+            Amount::try_new(dec!(1.500000), 4).unwrap().value(),
+            dec!(1.500000)
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use clap::ValueEnum;
+
+use crate::payment_engine::AccountStatus;
+
+/// Accounts are kept separately for each client and currency.
+pub type AccountKey = (u16, Option<String>);
+
+/// Storage of the client accounts used by the [`PaymentEngine`](super::PaymentEngine).
+pub trait ClientStore: Debug + Send {
+    fn get(&self, key: &AccountKey) -> Option<&AccountStatus>;
+    fn get_mut(&mut self, key: &AccountKey) -> Option<&mut AccountStatus>;
+    /// Returns the account stored under `key`, inserting the one created by `default` if there is
+    /// none yet.
+    fn get_or_insert_with(
+        &mut self,
+        key: AccountKey,
+        default: &dyn Fn() -> AccountStatus,
+    ) -> &mut AccountStatus;
+    fn accounts(&self) -> Box<dyn Iterator<Item = &AccountStatus> + '_>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ClientStoreKind {
+    /// Accounts are kept in a hash map.
+    #[default]
+    Hashmap,
+    /// Accounts are kept in a vector sorted by client. Suitable for a small number of clients.
+    Vec,
+}
+
+impl ClientStoreKind {
+    pub(super) fn build(self) -> Box<dyn ClientStore> {
+        match self {
+            ClientStoreKind::Hashmap => Box::new(HashMapClientStore::default()),
+            ClientStoreKind::Vec => Box::new(VecClientStore::default()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HashMapClientStore {
+    accounts: HashMap<AccountKey, AccountStatus>,
+}
+
+impl ClientStore for HashMapClientStore {
+    fn get(&self, key: &AccountKey) -> Option<&AccountStatus> {
+        self.accounts.get(key)
+    }
+
+    fn get_mut(&mut self, key: &AccountKey) -> Option<&mut AccountStatus> {
+        self.accounts.get_mut(key)
+    }
+
+    fn get_or_insert_with(
+        &mut self,
+        key: AccountKey,
+        default: &dyn Fn() -> AccountStatus,
+    ) -> &mut AccountStatus {
+        self.accounts.entry(key).or_insert_with(default)
+    }
+
+    fn accounts(&self) -> Box<dyn Iterator<Item = &AccountStatus> + '_> {
+        Box::new(self.accounts.values())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct VecClientStore {
+    /// Always sorted by the key.
+    accounts: Vec<(AccountKey, AccountStatus)>,
+}
+
+impl VecClientStore {
+    fn position(&self, key: &AccountKey) -> Result<usize, usize> {
+        self.accounts.binary_search_by(|(k, _)| k.cmp(key))
+    }
+}
+
+impl ClientStore for VecClientStore {
+    fn get(&self, key: &AccountKey) -> Option<&AccountStatus> {
+        let index = self.position(key).ok()?;
+        Some(&self.accounts[index].1)
+    }
+
+    fn get_mut(&mut self, key: &AccountKey) -> Option<&mut AccountStatus> {
+        let index = self.position(key).ok()?;
+        Some(&mut self.accounts[index].1)
+    }
+
+    fn get_or_insert_with(
+        &mut self,
+        key: AccountKey,
+        default: &dyn Fn() -> AccountStatus,
+    ) -> &mut AccountStatus {
+        let index = match self.position(&key) {
+            Ok(index) => index,
+            Err(index) => {
+                self.accounts.insert(index, (key, default()));
+                index
+            }
+        };
+        &mut self.accounts[index].1
+    }
+
+    fn accounts(&self) -> Box<dyn Iterator<Item = &AccountStatus> + '_> {
+        Box::new(self.accounts.iter().map(|(_, account)| account))
+    }
+}
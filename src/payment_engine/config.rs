@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+use std::num::NonZeroU64;
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use rust_decimal::Decimal;
+
+use crate::payment_engine::{ClientStoreKind, TransactionStoreKind};
+
+#[derive(Debug, Clone, Default)]
+pub struct PaymentEngineConfig {
+    /// Maximum amount by which a withdrawal may exceed the available funds.
+    /// The available funds are clamped to zero in such a case.
+    pub withdrawal_epsilon: Decimal,
+    /// Reject withdrawals from clients that have not made any successful deposit yet.
+    pub deny_withdrawal_before_deposit: bool,
+    /// Maximum funds which may be held on a single account due to disputes.
+    pub max_held: Option<Decimal>,
+    /// Reject resolves which would make the held funds of an account negative.
+    pub guard_releases: bool,
+    /// Accept identical re-sends of already processed deposits/withdrawals as no-ops and reject
+    /// conflicting reuses of their ids with a `DuplicateTransaction` error.
+    pub idempotent_retries: bool,
+    /// Whether dispute operations are accepted on locked accounts.
+    pub disputes_on_locked: DisputesOnLocked,
+    /// Storage backing the client accounts.
+    pub client_store: ClientStoreKind,
+    /// Storage backing the processed transactions.
+    pub transaction_store: TransactionStoreKind,
+    /// Treatment of resolves/chargebacks of transactions which are not disputed.
+    pub on_undisputed: OnUndisputed,
+    /// Ignore operations on locked accounts instead of rejecting them with `AccountLocked`.
+    pub treat_locked_as_readonly: bool,
+    /// Reject deposits and withdrawals to accounts which were not opened by an `OpenAccount`
+    /// transaction.
+    pub require_explicit_open: bool,
+    /// Reject withdrawals and chargebacks which would make the total funds of an account negative.
+    pub no_negative_total: bool,
+    /// Reverse the funds of a chargeback without locking the account.
+    pub no_lock_on_chargeback: bool,
+    /// Reject disputes of transactions with a zero amount, which would hold no funds.
+    pub reject_zero_amount_disputes: bool,
+    /// Accept disputes of already disputed transactions as no-ops instead of rejecting them with
+    /// `TransactionAlreadyDisputed`, e.g. for feeds which re-send them.
+    pub ignore_redundant_disputes: bool,
+    /// Reject disputes which would make the held funds of an account exceed its total funds.
+    pub enforce_held_le_total: bool,
+    /// Automatically resolve the oldest open disputes of an account whose held funds exceed this
+    /// amount after a dispute.
+    pub auto_resolve_over: Option<Decimal>,
+    /// Forget the stored deposits and withdrawals which are neither disputed nor pending once this
+    /// many transactions were processed after them, so that they can no longer be disputed.
+    pub forget_settled_after: Option<NonZeroU64>,
+    /// Match dispute operations carrying a reference with the transaction of the same reference
+    /// instead of the one of the same id.
+    pub dispute_by_ref: bool,
+    /// Zero out the held funds left after a chargeback if they are smaller than the smallest
+    /// amount a transaction can have, as such a residue cannot belong to any open dispute.
+    pub clamp_chargeback_residue: bool,
+    /// Do not log a warning when the engine adjusts a balance on its own, e.g. by clearing a
+    /// chargeback residue.
+    pub quiet: bool,
+    /// Record the operations applied to each account with the resulting balances, see
+    /// [`AccountStatus::journal`](super::AccountStatus::journal).
+    pub journal: bool,
+    /// Names of the transaction types (see [`TransactionType::name`](super::TransactionType::name))
+    /// which are rejected with an `OperationDisabled` error.
+    pub disabled_types: HashSet<&'static str>,
+    /// Source of the time at which an account gets locked, see
+    /// [`AccountStatus::locked_at`](super::AccountStatus::locked_at). Without one, the time is not
+    /// recorded, which keeps the processing deterministic.
+    pub clock: Option<fn() -> DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DisputesOnLocked {
+    /// Dispute operations are processed as usual.
+    #[default]
+    Allow,
+    /// Dispute operations are rejected with an `AccountLocked` error.
+    Deny,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnUndisputed {
+    /// The operation fails with a `NotDisputed` error, which aborts the processing.
+    Error,
+    /// The operation fails with a `NotDisputed` error and the processing continues.
+    #[default]
+    Warn,
+    /// The operation is silently ignored.
+    Ignore,
+}
@@ -0,0 +1,451 @@
+use std::str::FromStr;
+
+use csv::{ReaderBuilder, StringRecord};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde::de::{self, Deserializer};
+use thiserror::Error;
+
+use crate::payment_engine::{Amount, PaymentEngineError, Transaction, TransactionType};
+
+/// A single record of the transactions CSV.
+#[derive(Debug, Deserialize)]
+pub struct CsvTransaction {
+    #[serde(rename = "type")]
+    pub transaction_type: CsvTransactionType,
+    pub client: u16,
+    pub tx: u32,
+    #[serde(default, deserialize_with = "deserialize_amount")]
+    pub amount: Option<Decimal>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default, rename = "ref")]
+    pub reference: Option<String>,
+}
+
+/// Deserializes an optional amount, treating empty or whitespace-only fields as `None` and
+/// ignoring any whitespace surrounding the value.
+fn deserialize_amount<'de, D>(deserializer: D) -> Result<Option<Decimal>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(amount) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    let amount = amount.trim();
+    let amount = amount
+        .strip_prefix('"')
+        .and_then(|amount| amount.strip_suffix('"'))
+        .map_or(amount, str::trim);
+    if amount.is_empty() {
+        return Ok(None);
+    }
+
+    // trailing sign notation, e.g. `42.50-`
+    let (amount, negative) = match amount.strip_suffix('-') {
+        Some(unsigned) if !unsigned.starts_with(['-', '+']) => (unsigned, true),
+        _ => (amount, false),
+    };
+
+    Decimal::from_str(amount)
+        .map(|amount| Some(if negative { -amount } else { amount }))
+        .map_err(|err| {
+            if amount.contains(['e', 'E']) {
+                de::Error::custom(format!(
+                    "amount {amount} is in scientific notation, which is not accepted"
+                ))
+            } else {
+                de::Error::custom(err)
+            }
+        })
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CsvTransactionType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    Pending,
+    Settle,
+    Open,
+    Freeze,
+    Unfreeze,
+    #[serde(rename = "pending_fee")]
+    PendingFee,
+}
+
+/// Columns of a record without a header, in the order of the input files.
+const COLUMNS: [&str; 6] = ["type", "client", "tx", "amount", "currency", "ref"];
+/// Columns every input with a header must have.
+const REQUIRED_COLUMNS: [&str; 3] = ["type", "client", "tx"];
+
+impl CsvTransaction {
+    /// Checks that the `headers` contain all the required columns and none of the known columns
+    /// more than once, as the records could not be deserialized reliably otherwise. Unknown
+    /// columns are ignored.
+    pub fn validate_headers(headers: &StringRecord) -> Result<(), CsvTransactionError> {
+        let count = |column: &str| headers.iter().filter(|header| *header == column).count();
+        let missing = REQUIRED_COLUMNS
+            .into_iter()
+            .filter(|column| count(column) == 0)
+            .collect::<Vec<_>>();
+        let duplicated = COLUMNS
+            .into_iter()
+            .filter(|column| count(column) > 1)
+            .collect::<Vec<_>>();
+
+        if missing.is_empty() && duplicated.is_empty() {
+            Ok(())
+        } else {
+            Err(CsvTransactionError::InvalidHeaders {
+                missing,
+                duplicated,
+            })
+        }
+    }
+
+    /// Parses a single record without a header, e.g. `deposit,1,1,100.0`. The trailing `amount`,
+    /// `currency` and `ref` fields are optional.
+    pub fn from_line(line: &str) -> Result<Self, CsvTransactionError> {
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(line.as_bytes());
+        let record = reader.records().next().transpose()?.unwrap_or_default();
+
+        Self::from_record(&record, &StringRecord::from(COLUMNS.to_vec()))
+    }
+
+    /// Deserializes the `record` with the given `headers`. Client ids which do not fit into `u16`
+    /// are reported as [`CsvTransactionError::ClientOutOfRange`] instead of a generic
+    /// deserialization error.
+    pub fn from_record(
+        record: &StringRecord,
+        headers: &StringRecord,
+    ) -> Result<Self, CsvTransactionError> {
+        record.deserialize(Some(headers)).map_err(|err| {
+            let client = headers
+                .iter()
+                .position(|header| header == "client")
+                .and_then(|index| record.get(index))
+                .and_then(|client| client.trim().parse::<u64>().ok());
+            match client {
+                Some(client) if client > u64::from(u16::MAX) => {
+                    CsvTransactionError::ClientOutOfRange {
+                        line: record.position().map_or(0, |position| position.line()),
+                        client,
+                    }
+                }
+                _ => err.into(),
+            }
+        })
+    }
+
+    /// Deserializes a JSON object with the fields of the CSV columns, e.g.
+    /// `{"type":"deposit","client":1,"tx":1,"amount":"100.0"}`. The amount must be a string to
+    /// preserve its precision. Client ids which do not fit into `u16` are reported as
+    /// [`CsvTransactionError::ClientOutOfRange`] like in [`CsvTransaction::from_record`].
+    pub fn from_json_line(json: &str, line: u64) -> Result<Self, CsvTransactionError> {
+        serde_json::from_str(json).map_err(|source| {
+            let client = serde_json::from_str::<serde_json::Value>(json)
+                .ok()
+                .and_then(|value| value.get("client")?.as_u64());
+            match client {
+                Some(client) if client > u64::from(u16::MAX) => {
+                    CsvTransactionError::ClientOutOfRange { line, client }
+                }
+                _ => CsvTransactionError::Json { line, source },
+            }
+        })
+    }
+
+    /// Replaces the amount of a transaction which requires one with the `corrected_amount`, if any.
+    pub fn with_amount_override(mut self, corrected_amount: Option<Decimal>) -> Self {
+        if corrected_amount.is_some() && self.transaction_type.requires_amount() {
+            self.amount = corrected_amount;
+        }
+
+        self
+    }
+
+    /// Fills in `default_amount` if the transaction requires an amount, but none was provided.
+    pub fn with_default_amount(mut self, default_amount: Option<Decimal>) -> Self {
+        if self.amount.is_none() && self.transaction_type.requires_amount() {
+            self.amount = default_amount;
+        }
+
+        self
+    }
+
+    /// Replaces the amount with its absolute value if `abs_amounts` is set, so that the sign is
+    /// given only by the transaction type, e.g. `withdrawal,1,1,-50` withdraws 50.
+    pub fn with_abs_amount(mut self, abs_amounts: bool) -> Self {
+        if abs_amounts {
+            self.amount = self.amount.map(|amount| amount.abs());
+        }
+
+        self
+    }
+}
+
+impl CsvTransactionType {
+    pub fn requires_amount(&self) -> bool {
+        matches!(
+            self,
+            CsvTransactionType::Deposit
+                | CsvTransactionType::Withdrawal
+                | CsvTransactionType::Pending
+                | CsvTransactionType::PendingFee
+        )
+    }
+}
+
+impl TryFrom<CsvTransaction> for Transaction {
+    type Error = CsvTransactionError;
+
+    fn try_from(value: CsvTransaction) -> Result<Self, Self::Error> {
+        let amount = |name| {
+            let amount = value
+                .amount
+                .ok_or(CsvTransactionError::MissingAmount(name))?;
+            Ok::<_, CsvTransactionError>(Amount::try_new(amount, Amount::MAX_SCALE)?)
+        };
+
+        let transaction_type = match value.transaction_type {
+            CsvTransactionType::Deposit => TransactionType::Deposit {
+                amount: amount("deposit")?,
+            },
+            CsvTransactionType::Withdrawal => TransactionType::Withdrawal {
+                amount: amount("withdrawal")?,
+            },
+            CsvTransactionType::Dispute => TransactionType::Dispute,
+            CsvTransactionType::Resolve => TransactionType::Resolve,
+            CsvTransactionType::Chargeback => TransactionType::Chargeback,
+            CsvTransactionType::Pending => TransactionType::PendingDeposit {
+                amount: amount("pending deposit")?,
+            },
+            CsvTransactionType::Settle => TransactionType::Settle,
+            CsvTransactionType::Open => TransactionType::OpenAccount,
+            CsvTransactionType::Freeze => TransactionType::FreezeWithdrawals,
+            CsvTransactionType::Unfreeze => TransactionType::UnfreezeWithdrawals,
+            CsvTransactionType::PendingFee => TransactionType::PendingFee {
+                amount: amount("pending fee")?,
+            },
+        };
+
+        Ok(Transaction::new(value.client, value.tx, transaction_type)
+            .with_currency(value.currency)
+            .with_reference(value.reference))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CsvTransactionError {
+    #[error("invalid transaction record: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("invalid transaction on line {line}: {source}")]
+    Json {
+        line: u64,
+        source: serde_json::Error,
+    },
+    #[error("amount is required for {0}")]
+    MissingAmount(&'static str),
+    #[error("client {client} on line {line} is out of range (at most 65535)")]
+    ClientOutOfRange { line: u64, client: u64 },
+    #[error(
+        "amount {amount} on line {line} is not in the `1.234,56` notation (dots may only separate thousands before a decimal comma)"
+    )]
+    InvalidLocalizedAmount { line: u64, amount: String },
+    #[error(
+        "invalid header (missing columns: [{}], duplicated columns: [{}])",
+        missing.join(", "),
+        duplicated.join(", ")
+    )]
+    InvalidHeaders {
+        missing: Vec<&'static str>,
+        duplicated: Vec<&'static str>,
+    },
+    /// The record is well-formed, but the transaction is invalid or was rejected by the engine.
+    #[error(transparent)]
+    Engine(#[from] PaymentEngineError),
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use super::*;
+
+    #[test]
+    fn test_deserialize_deposit() {
+        let data = "type,client,tx,amount\n\
+                    deposit,1,1001,42.5\n";
+        let mut reader = ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(data.as_bytes());
+        let mut iter = reader.deserialize::<CsvTransaction>();
+        let tx = iter.next().unwrap().unwrap();
+        assert_eq!(tx.transaction_type, CsvTransactionType::Deposit);
+        assert_eq!(tx.client, 1);
+        assert_eq!(tx.tx, 1001);
+        assert_eq!(tx.amount, Some(dec!(42.5)));
+    }
+
+    #[test]
+    fn test_deserialize_open() {
+        let data = "type,client,tx,amount\n\
+                    open,3,7,\n";
+        let mut reader = ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(data.as_bytes());
+        let mut iter = reader.deserialize::<CsvTransaction>();
+        let tx = iter.next().unwrap().unwrap();
+        assert_eq!(tx.transaction_type, CsvTransactionType::Open);
+        assert_eq!(tx.amount, None);
+
+        let transaction = Transaction::try_from(tx).unwrap();
+        assert!(matches!(transaction.r#type, TransactionType::OpenAccount));
+        assert_eq!(transaction.client, 3);
+    }
+
+    #[test]
+    fn test_deserialize_pending_fee() {
+        let transaction = CsvTransaction::from_line("pending_fee,3,8,1.5").unwrap();
+        assert_eq!(transaction.transaction_type, CsvTransactionType::PendingFee);
+
+        let transaction = Transaction::try_from(transaction).unwrap();
+        assert_eq!(transaction.r#type.amount().unwrap().value(), dec!(1.5));
+        assert!(matches!(
+            Transaction::try_from(CsvTransaction::from_line("pending_fee,3,9,").unwrap()),
+            Err(CsvTransactionError::MissingAmount("pending fee"))
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_withdrawal() {
+        let data = "type,client,tx,amount\n\
+                    withdrawal,2,1002,10.0\n";
+        let mut reader = ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(data.as_bytes());
+        let mut iter = reader.deserialize::<CsvTransaction>();
+        let tx = iter.next().unwrap().unwrap();
+        assert_eq!(tx.transaction_type, CsvTransactionType::Withdrawal);
+        assert_eq!(tx.client, 2);
+        assert_eq!(tx.tx, 1002);
+        assert_eq!(tx.amount, Some(dec!(10.0)));
+    }
+
+    #[test]
+    fn test_deserialize_dispute() {
+        let data = "type,client,tx,amount\n\
+                    dispute,3,1003,\n";
+        let mut reader = ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(data.as_bytes());
+        let mut iter = reader.deserialize::<CsvTransaction>();
+        let tx = iter.next().unwrap().unwrap();
+        assert_eq!(tx.transaction_type, CsvTransactionType::Dispute);
+        assert_eq!(tx.client, 3);
+        assert_eq!(tx.tx, 1003);
+        assert_eq!(tx.amount, None);
+    }
+
+    fn deserialize_amount_field(amount: &str) -> Option<Decimal> {
+        let data = format!("type,client,tx,amount\ndispute,1,1,{amount}\n");
+        let mut reader = ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(data.as_bytes());
+        let mut iter = reader.deserialize::<CsvTransaction>();
+        iter.next().unwrap().unwrap().amount
+    }
+
+    fn deserialize_amount_error(amount: &str) -> String {
+        let data = format!("type,client,tx,amount\ndeposit,1,1,{amount}\n");
+        let mut reader = ReaderBuilder::new()
+            .has_headers(true)
+            .from_reader(data.as_bytes());
+        let mut iter = reader.deserialize::<CsvTransaction>();
+        iter.next().unwrap().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_deserialize_empty_amount() {
+        assert_eq!(deserialize_amount_field(""), None);
+    }
+
+    #[test]
+    fn test_deserialize_trailing_sign_amount() {
+        assert_eq!(deserialize_amount_field("42.50-"), Some(dec!(-42.50)));
+        assert_eq!(deserialize_amount_field("\"42.50-\""), Some(dec!(-42.50)));
+    }
+
+    #[test]
+    fn test_deserialize_quoted_amount() {
+        assert_eq!(deserialize_amount_field("\"42.50\""), Some(dec!(42.50)));
+        // quotes which are part of the field value
+        assert_eq!(
+            deserialize_amount_field("\"\"\"42.50\"\"\""),
+            Some(dec!(42.50))
+        );
+        assert_eq!(
+            deserialize_amount_field("\" \"\"42.50-\"\"\""),
+            Some(dec!(-42.50))
+        );
+    }
+
+    #[test]
+    fn test_deserialize_double_sign_amount() {
+        let data = "type,client,tx,amount\ndeposit,1,1,-42.50-\n";
+        let mut reader = ReaderBuilder::new().from_reader(data.as_bytes());
+        assert!(
+            reader
+                .deserialize::<CsvTransaction>()
+                .next()
+                .unwrap()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_deserialize_whitespace_amount() {
+        assert_eq!(deserialize_amount_field("   "), None);
+    }
+
+    #[test]
+    fn test_deserialize_padded_amount() {
+        assert_eq!(deserialize_amount_field(" 12.5 "), Some(dec!(12.5)));
+    }
+
+    #[test]
+    fn test_client_out_of_range() {
+        let headers = StringRecord::from(vec!["type", "client", "tx", "amount"]);
+        let mut record = StringRecord::from(vec!["deposit", "70000", "1", "10.0"]);
+        let mut position = csv::Position::new();
+        position.set_line(2);
+        record.set_position(Some(position));
+        let err = CsvTransaction::from_record(&record, &headers).unwrap_err();
+        assert!(matches!(
+            err,
+            CsvTransactionError::ClientOutOfRange {
+                line: 2,
+                client: 70000
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "client 70000 on line 2 is out of range (at most 65535)"
+        );
+    }
+
+    #[test]
+    fn test_scientific_notation() {
+        for amount in ["1e3", "1.5E2"] {
+            let err = deserialize_amount_error(amount);
+            assert!(err.contains("scientific notation"), "{err}");
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt::Debug;
+
+use crate::payment_engine::{AccountStatus, Transaction};
+
+/// Callbacks invoked by the [`PaymentEngine`](super::PaymentEngine) around the processing of a
+/// transaction: [`before_transaction`](Self::before_transaction) before any transaction is
+/// applied, e.g. for custom validation, and the `on_*` method of the transaction type after it was
+/// successfully applied to the client's account.
+///
+/// All methods default to no-op, so implementors only need to override the ones they care about.
+pub trait TransactionHook: Debug + Send {
+    /// Called before the `transaction` is applied, with the current state of the account it
+    /// applies to, if the account exists. Returning an error rejects the transaction with
+    /// `RejectedByHook`, leaving the engine unchanged.
+    fn before_transaction(
+        &mut self,
+        _transaction: &Transaction,
+        _account: Option<&AccountStatus>,
+    ) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn on_deposit(&mut self, _transaction: &Transaction, _account: &AccountStatus) {}
+    fn on_withdrawal(&mut self, _transaction: &Transaction, _account: &AccountStatus) {}
+    fn on_dispute(&mut self, _transaction: &Transaction, _account: &AccountStatus) {}
+    fn on_resolve(&mut self, _transaction: &Transaction, _account: &AccountStatus) {}
+    fn on_chargeback(&mut self, _transaction: &Transaction, _account: &AccountStatus) {}
+    fn on_pending_deposit(&mut self, _transaction: &Transaction, _account: &AccountStatus) {}
+    fn on_settle(&mut self, _transaction: &Transaction, _account: &AccountStatus) {}
+    fn on_open_account(&mut self, _transaction: &Transaction, _account: &AccountStatus) {}
+    fn on_freeze_withdrawals(&mut self, _transaction: &Transaction, _account: &AccountStatus) {}
+    fn on_unfreeze_withdrawals(&mut self, _transaction: &Transaction, _account: &AccountStatus) {}
+    fn on_pending_fee(&mut self, _transaction: &Transaction, _account: &AccountStatus) {}
+}
@@ -1,19 +1,58 @@
 mod account;
+mod amount;
+mod client_store;
+mod config;
+mod csv_record;
+mod hook;
 mod transaction;
+mod transaction_store;
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
 
+pub use account::{AccountStatus, JournalEntry};
+pub use amount::Amount;
+use client_store::AccountKey;
+pub use client_store::{ClientStore, ClientStoreKind};
+pub use config::{DisputesOnLocked, OnUndisputed, PaymentEngineConfig};
+pub use csv_record::{CsvTransaction, CsvTransactionError, CsvTransactionType};
+pub use hook::TransactionHook;
 use rust_decimal::Decimal;
+use serde::Serialize;
 use thiserror::Error;
-pub use transaction::Transaction;
+use tracing::{info, warn};
 pub use transaction::TransactionType;
+pub use transaction::{DisputeEvent, Transaction};
+pub use transaction_store::{TransactionStore, TransactionStoreKind};
 
-use crate::payment_engine::account::AccountStatus;
-
-#[derive(Debug, Clone, Default)]
+#[derive(Debug)]
 pub struct PaymentEngine {
-    clients: HashMap<u16, AccountStatus>,
-    transactions: HashMap<u32, Transaction>,
+    config: PaymentEngineConfig,
+    hook: Option<Box<dyn TransactionHook>>,
+    clients: Box<dyn ClientStore>,
+    transactions: Box<dyn TransactionStore>,
+    /// Ids of the open disputes of each account, oldest first. Tracked only with
+    /// `auto_resolve_over`.
+    dispute_queues: HashMap<AccountKey, Vec<u32>>,
+    /// Number of the transactions passed to [`PaymentEngine::process_transaction`].
+    processed: u64,
+    /// Ids of the stored transactions together with the value of `processed` at the time they
+    /// were stored, oldest first. Tracked only with `forget_settled_after`.
+    stored_order: VecDeque<(u64, u32)>,
+    /// Ids of the transactions removed by `forget_settled_after`, which take up far less memory
+    /// than the transactions themselves.
+    forgotten: HashSet<u32>,
+    /// Ids of the stored transactions by their reference. Tracked only with `dispute_by_ref`.
+    references: HashMap<String, u32>,
+    /// Kinds of the transactions referenced by the disputes.
+    dispute_targets: DisputeTargets,
+}
+
+impl Default for PaymentEngine {
+    fn default() -> Self {
+        Self::with_config(PaymentEngineConfig::default())
+    }
 }
 
 impl PaymentEngine {
@@ -21,40 +60,298 @@ impl PaymentEngine {
         Self::default()
     }
 
+    /// Creates an engine with the given `config`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the transaction store cannot be created, see [`PaymentEngine::try_with_config`].
+    pub fn with_config(config: PaymentEngineConfig) -> Self {
+        Self::try_with_config(config).expect("failed to create the transaction store")
+    }
+
+    /// Creates an engine with the given `config`, failing if the transaction store cannot be
+    /// created, e.g. as its file cannot be written.
+    pub fn try_with_config(config: PaymentEngineConfig) -> io::Result<Self> {
+        Ok(Self {
+            clients: config.client_store.build(),
+            transactions: config.transaction_store.build()?,
+            config,
+            hook: None,
+            dispute_queues: HashMap::new(),
+            processed: 0,
+            stored_order: VecDeque::new(),
+            forgotten: HashSet::new(),
+            references: HashMap::new(),
+            dispute_targets: DisputeTargets::default(),
+        })
+    }
+
+    /// Builds an engine by processing all the `records`. The ids of the transactions which failed
+    /// to be processed are returned alongside the engine together with the errors.
+    pub fn from_records<I>(records: I) -> (Self, Vec<(u32, PaymentEngineError)>)
+    where
+        I: IntoIterator<Item = Transaction>,
+    {
+        let mut engine = Self::new();
+        let mut failures = Vec::new();
+        for transaction in records {
+            let transaction_id = transaction.id;
+            if let Err(err) = engine.process_transaction(transaction) {
+                failures.push((transaction_id, err));
+            }
+        }
+
+        (engine, failures)
+    }
+
+    /// Configuration the engine was created with.
+    pub fn config(&self) -> &PaymentEngineConfig {
+        &self.config
+    }
+
+    /// Registers a hook which gets notified about every successfully applied transaction.
+    pub fn set_hook(&mut self, hook: impl TransactionHook + 'static) {
+        self.hook = Some(Box::new(hook));
+    }
+
+    /// Parses a single CSV record without a header (see [`CsvTransaction::from_line`]) and
+    /// processes the transaction. Returns the processed transaction.
+    pub fn process_csv_line(&mut self, line: &str) -> Result<Transaction, CsvTransactionError> {
+        let transaction: Transaction = CsvTransaction::from_line(line)?.try_into()?;
+        self.process_transaction(transaction.clone())?;
+
+        Ok(transaction)
+    }
+
     pub fn process_transaction(
         &mut self,
-        transaction: Transaction,
+        mut transaction: Transaction,
     ) -> Result<(), PaymentEngineError> {
-        let client = self
-            .clients
-            .entry(transaction.client)
-            .or_insert_with(|| AccountStatus::new(transaction.client));
+        let type_name = transaction.r#type.name();
+        if self.config.disabled_types.contains(type_name) {
+            return Err(PaymentEngineError::OperationDisabled(type_name));
+        }
+        if let Some(hook) = &mut self.hook {
+            let account = self
+                .clients
+                .get(&(transaction.client, transaction.currency.clone()));
+            hook.before_transaction(&transaction, account)
+                .map_err(|reason| PaymentEngineError::RejectedByHook(transaction.id, reason))?;
+        }
 
-        match transaction.r#type {
-            TransactionType::Deposit { amount } => {
-                if amount < Decimal::ZERO {
-                    return Err(PaymentEngineError::InvalidAmount(
-                        amount,
-                        "deposit amount cannot be negative".to_string(),
-                    ));
+        self.processed += 1;
+        if let Some(forget_after) = self.config.forget_settled_after {
+            self.forget_settled(forget_after.get());
+        }
+
+        if self.config.dispute_by_ref
+            && matches!(
+                transaction.r#type,
+                TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+            )
+            && let Some(reference) = &transaction.reference
+        {
+            let Some(&transaction_id) = self.references.get(reference) else {
+                if transaction.r#type == TransactionType::Dispute {
+                    self.dispute_targets.missing += 1;
+                }
+                return Err(PaymentEngineError::UnknownReference(reference.clone()));
+            };
+            transaction.id = transaction_id;
+        }
+
+        if transaction.r#type == TransactionType::Dispute {
+            let target = self.transactions.get(transaction.id);
+            self.dispute_targets
+                .record(target.as_ref().map(|target| &target.r#type));
+        }
+
+        if self.config.idempotent_retries
+            && matches!(
+                transaction.r#type,
+                TransactionType::Deposit { .. }
+                    | TransactionType::Withdrawal { .. }
+                    | TransactionType::PendingDeposit { .. }
+            )
+            && let Some(stored_transaction) = self.transactions.get(transaction.id)
+        {
+            if transaction.is_retry_of(&stored_transaction) {
+                return Ok(());
+            }
+            return Err(PaymentEngineError::DuplicateTransaction(transaction.id));
+        }
+
+        let transaction_id = transaction.id;
+        let is_dispute = transaction.r#type == TransactionType::Dispute;
+        let is_stored = matches!(
+            transaction.r#type,
+            TransactionType::Deposit { .. }
+                | TransactionType::Withdrawal { .. }
+                | TransactionType::PendingDeposit { .. }
+        );
+        let reference = self
+            .config
+            .dispute_by_ref
+            .then(|| transaction.reference.clone())
+            .flatten();
+        let journal_entry = self.config.journal.then(|| {
+            let amount = transaction.r#type.amount().or_else(|| {
+                self.transactions
+                    .get(transaction.id)
+                    .and_then(|original_transaction| original_transaction.r#type.amount())
+            });
+            (transaction.r#type.name(), amount.map(Amount::value))
+        });
+
+        let key = (transaction.client, transaction.currency.clone());
+        // checked before the account is created, so that a rejected transaction leaves no account
+        if self.config.require_explicit_open
+            && matches!(
+                transaction.r#type,
+                TransactionType::Deposit { .. }
+                    | TransactionType::Withdrawal { .. }
+                    | TransactionType::PendingDeposit { .. }
+            )
+            && !self.clients.get(&key).is_some_and(|client| client.is_open)
+        {
+            return Err(PaymentEngineError::AccountNotOpened(transaction.client));
+        }
+
+        let client = match transaction.r#type {
+            // disputes can only refer to existing accounts, never create new ones
+            TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
+                self.clients
+                    .get_mut(&key)
+                    .ok_or(PaymentEngineError::UnknownClient(transaction.client))?
+            }
+            _ => self
+                .clients
+                .get_or_insert_with(key.clone(), &|| AccountStatus {
+                    currency: transaction.currency.clone(),
+                    ..AccountStatus::new(transaction.client)
+                }),
+        };
+
+        if self.config.treat_locked_as_readonly && client.locked {
+            // operations which would be rejected with `AccountLocked` are no-ops instead
+            let rejected = match transaction.r#type {
+                TransactionType::Deposit { .. }
+                | TransactionType::Withdrawal { .. }
+                | TransactionType::PendingDeposit { .. } => true,
+                TransactionType::Dispute
+                | TransactionType::Resolve
+                | TransactionType::Chargeback => {
+                    self.config.disputes_on_locked == DisputesOnLocked::Deny
                 }
+                TransactionType::Settle
+                | TransactionType::OpenAccount
+                | TransactionType::FreezeWithdrawals
+                | TransactionType::UnfreezeWithdrawals
+                | TransactionType::PendingFee { .. } => false,
+            };
+            if rejected {
+                return Ok(());
+            }
+        }
 
+        match transaction.r#type {
+            TransactionType::Deposit { amount } => {
+                let amount = amount.value();
                 client.deposit(amount)?;
-                self.transactions.insert(transaction.id, transaction);
+                client.gross_deposits += amount;
+                if let Some(hook) = &mut self.hook {
+                    hook.on_deposit(&transaction, client);
+                }
+                self.transactions.insert(transaction)?;
             }
             TransactionType::Withdrawal { amount } => {
-                if amount < Decimal::ZERO {
-                    return Err(PaymentEngineError::InvalidAmount(
-                        amount,
-                        "withdrawal amount cannot be negative".to_string(),
+                let amount = amount.value();
+                if self.config.deny_withdrawal_before_deposit && !client.has_deposited {
+                    return Err(PaymentEngineError::WithdrawalBeforeDeposit(client.client));
+                }
+
+                let withdrawn = client.withdraw(
+                    amount,
+                    self.config.withdrawal_epsilon,
+                    self.config.no_negative_total,
+                )?;
+                client.gross_withdrawals += withdrawn;
+                // a dispute of a withdrawal within the epsilon returns only the withdrawn funds
+                let transaction = transaction.with_amount(Amount::new_unchecked(withdrawn));
+                if let Some(hook) = &mut self.hook {
+                    hook.on_withdrawal(&transaction, client);
+                }
+                self.transactions.insert(transaction)?;
+            }
+            TransactionType::PendingDeposit { amount } => {
+                let amount = amount.value();
+                client.pending_deposit(amount)?;
+                if let Some(hook) = &mut self.hook {
+                    hook.on_pending_deposit(&transaction, client);
+                }
+                let mut transaction = transaction;
+                transaction.is_pending = true;
+                self.transactions.insert(transaction)?;
+            }
+            TransactionType::OpenAccount => {
+                client.is_open = true;
+                if let Some(hook) = &mut self.hook {
+                    hook.on_open_account(&transaction, client);
+                }
+            }
+            TransactionType::FreezeWithdrawals => {
+                client.withdrawals_frozen = true;
+                if let Some(hook) = &mut self.hook {
+                    hook.on_freeze_withdrawals(&transaction, client);
+                }
+            }
+            TransactionType::UnfreezeWithdrawals => {
+                client.withdrawals_frozen = false;
+                if let Some(hook) = &mut self.hook {
+                    hook.on_unfreeze_withdrawals(&transaction, client);
+                }
+            }
+            TransactionType::PendingFee { amount } => {
+                client.pending_fees += amount.value();
+                if let Some(hook) = &mut self.hook {
+                    hook.on_pending_fee(&transaction, client);
+                }
+            }
+            TransactionType::Settle => {
+                let Some(mut original_transaction) =
+                    self.transactions.get(transaction.id).map(Cow::into_owned)
+                else {
+                    return Err(PaymentEngineError::TransactionNotFound(transaction.id));
+                };
+
+                if original_transaction.client != transaction.client {
+                    return Err(PaymentEngineError::SettleForDifferentClient);
+                }
+
+                if original_transaction.currency != transaction.currency {
+                    return Err(PaymentEngineError::CurrencyMismatch(transaction.id));
+                }
+
+                let TransactionType::PendingDeposit { amount } = original_transaction.r#type else {
+                    return Err(PaymentEngineError::InvalidTransactionType(
+                        "settle can only be applied to pending deposit".to_string(),
                     ));
+                };
+                if !original_transaction.is_pending {
+                    return Err(PaymentEngineError::NotPending(transaction.id));
                 }
 
-                client.withdraw(amount)?;
-                self.transactions.insert(transaction.id, transaction);
+                original_transaction.is_pending = false;
+                self.transactions.insert(original_transaction)?;
+                client.settle(amount.value());
+                if let Some(hook) = &mut self.hook {
+                    hook.on_settle(&transaction, client);
+                }
             }
             TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback => {
-                let Some(original_transaction) = self.transactions.get_mut(&transaction.id) else {
+                let Some(mut original_transaction) =
+                    self.transactions.get(transaction.id).map(Cow::into_owned)
+                else {
                     return Err(PaymentEngineError::TransactionNotFound(transaction.id));
                 };
 
@@ -62,9 +359,27 @@ impl PaymentEngine {
                     return Err(PaymentEngineError::DisputeForDifferentClient);
                 }
 
+                if original_transaction.currency != transaction.currency {
+                    return Err(PaymentEngineError::CurrencyMismatch(transaction.id));
+                }
+
+                if client.locked && self.config.disputes_on_locked == DisputesOnLocked::Deny {
+                    return Err(PaymentEngineError::AccountLocked(client.client));
+                }
+
                 let amount = match original_transaction.r#type {
-                    TransactionType::Deposit { amount } => amount,
-                    TransactionType::Withdrawal { amount } => -amount,
+                    TransactionType::Deposit { amount } => amount.value(),
+                    TransactionType::Withdrawal { amount } => -amount.value(),
+                    TransactionType::PendingDeposit { amount }
+                        if !original_transaction.is_pending =>
+                    {
+                        amount.value()
+                    }
+                    TransactionType::PendingDeposit { .. } => {
+                        return Err(PaymentEngineError::InvalidTransactionType(
+                            "pending deposit must be settled before being disputed".to_string(),
+                        ));
+                    }
                     _ => {
                         return Err(PaymentEngineError::InvalidTransactionType(
                             "dispute can only be applied to deposit or withdrawal".to_string(),
@@ -75,42 +390,391 @@ impl PaymentEngine {
                 match transaction.r#type {
                     TransactionType::Dispute => {
                         if original_transaction.is_disputed {
+                            if self.config.ignore_redundant_disputes {
+                                return Ok(());
+                            }
                             return Err(PaymentEngineError::TransactionAlreadyDisputed(
                                 transaction.id,
                             ));
                         }
 
-                        original_transaction.is_disputed = true;
+                        if self.config.reject_zero_amount_disputes && amount.is_zero() {
+                            return Err(PaymentEngineError::ZeroAmountDispute(transaction.id));
+                        }
+
+                        if let Some(max_held) = self.config.max_held
+                            && client.held + amount > max_held
+                        {
+                            return Err(PaymentEngineError::MaxHeldExceeded(client.client));
+                        }
+
+                        // possible when the disputed deposit was already withdrawn
+                        if self.config.enforce_held_le_total && client.held + amount > client.total
+                        {
+                            return Err(PaymentEngineError::HeldExceedsTotal(client.client));
+                        }
+
                         client.hold_funds(amount)?;
+                        original_transaction.is_disputed = true;
+                        original_transaction.lifecycle.push(DisputeEvent::Disputed);
+                        if let Some(hook) = &mut self.hook {
+                            hook.on_dispute(&transaction, client);
+                        }
                     }
                     TransactionType::Resolve => {
                         if !original_transaction.is_disputed {
+                            if self.config.on_undisputed == OnUndisputed::Ignore {
+                                return Ok(());
+                            }
                             return Err(PaymentEngineError::NotDisputed(transaction.id));
                         }
 
+                        client.release_funds(amount, self.config.guard_releases)?;
                         original_transaction.is_disputed = false;
-                        client.release_funds(amount);
+                        original_transaction.lifecycle.push(DisputeEvent::Resolved);
+                        if let Some(queue) = self.dispute_queues.get_mut(&key) {
+                            queue.retain(|&id| id != transaction.id);
+                        }
+                        if let Some(hook) = &mut self.hook {
+                            hook.on_resolve(&transaction, client);
+                        }
                     }
                     TransactionType::Chargeback => {
                         if !original_transaction.is_disputed {
+                            if self.config.on_undisputed == OnUndisputed::Ignore {
+                                return Ok(());
+                            }
                             return Err(PaymentEngineError::NotDisputed(transaction.id));
                         }
 
+                        client.chargeback(
+                            amount,
+                            transaction.id,
+                            !self.config.no_lock_on_chargeback,
+                            self.config.no_negative_total,
+                            self.config.clock.map(|clock| clock()),
+                        )?;
                         original_transaction.is_disputed = false;
-                        client.chargeback(amount);
+                        original_transaction
+                            .lifecycle
+                            .push(DisputeEvent::ChargedBack);
+                        // the held funds are sums of amounts, so anything smaller than the
+                        // smallest amount is a residue, e.g. of a seeded account of a system
+                        // with more precise amounts
+                        if self.config.clamp_chargeback_residue
+                            && let Some(residue) = client.clamp_held_residue(
+                                Decimal::new(1, Amount::MAX_SCALE),
+                                self.config.no_negative_total,
+                            )
+                            && !self.config.quiet
+                        {
+                            warn!(
+                                client = client.client,
+                                transaction_id = transaction.id,
+                                %residue,
+                                "held funds residue cleared after a chargeback"
+                            );
+                        }
+                        if let Some(queue) = self.dispute_queues.get_mut(&key) {
+                            queue.retain(|&id| id != transaction.id);
+                        }
+                        if let Some(hook) = &mut self.hook {
+                            hook.on_chargeback(&transaction, client);
+                        }
                     }
-                    TransactionType::Deposit { .. } | TransactionType::Withdrawal { .. } => {
+                    TransactionType::Deposit { .. }
+                    | TransactionType::Withdrawal { .. }
+                    | TransactionType::PendingDeposit { .. }
+                    | TransactionType::Settle
+                    | TransactionType::OpenAccount
+                    | TransactionType::FreezeWithdrawals
+                    | TransactionType::UnfreezeWithdrawals
+                    | TransactionType::PendingFee { .. } => {
                         unreachable!()
                     }
                 }
+                self.transactions.insert(original_transaction)?;
+            }
+        }
+
+        if let Some((r#type, amount)) = journal_entry {
+            client.record(transaction_id, r#type, amount);
+        }
+
+        if is_dispute && let Some(max_held) = self.config.auto_resolve_over {
+            let queue = self.dispute_queues.entry(key).or_default();
+            queue.push(transaction_id);
+            Self::auto_resolve(
+                client,
+                queue,
+                self.transactions.as_mut(),
+                &mut self.hook,
+                max_held,
+                &self.config,
+            )?;
+        }
+
+        if is_stored && let Some(reference) = reference {
+            self.references.insert(reference, transaction_id);
+        }
+        if is_stored && self.config.forget_settled_after.is_some() {
+            self.stored_order
+                .push_back((self.processed, transaction_id));
+            self.forgotten.remove(&transaction_id);
+        }
+
+        Ok(())
+    }
+
+    /// Removes the stored transactions which were stored at least `forget_after` transactions ago.
+    /// Disputed and pending transactions are kept and their age starts anew, so that they are
+    /// forgotten only once they are settled.
+    fn forget_settled(&mut self, forget_after: u64) {
+        while let Some(&(stored_at, transaction_id)) = self.stored_order.front()
+            && self.processed - stored_at >= forget_after
+        {
+            self.stored_order.pop_front();
+            let is_open = self
+                .transactions
+                .get(transaction_id)
+                .map(|transaction| transaction.is_disputed || transaction.is_pending);
+            match is_open {
+                Some(true) => {
+                    self.stored_order
+                        .push_back((self.processed, transaction_id));
+                }
+                Some(false) => {
+                    self.forgotten.insert(transaction_id);
+                    let transaction = self.transactions.remove(transaction_id);
+                    // the reference might have been reused by a newer transaction
+                    if let Some(reference) =
+                        transaction.and_then(|transaction| transaction.reference)
+                        && self.references.get(&reference) == Some(&transaction_id)
+                    {
+                        self.references.remove(&reference);
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Resolves the oldest open disputes of the `client` until its held funds no longer exceed
+    /// `max_held`. The newest dispute is never resolved, and disputed withdrawals are skipped as
+    /// resolving them would increase the held funds. So are the disputes whose release is rejected
+    /// by `guard_releases`, as the triggering dispute was already applied.
+    fn auto_resolve(
+        client: &mut AccountStatus,
+        queue: &mut Vec<u32>,
+        transactions: &mut dyn TransactionStore,
+        hook: &mut Option<Box<dyn TransactionHook>>,
+        max_held: Decimal,
+        config: &PaymentEngineConfig,
+    ) -> Result<(), PaymentEngineError> {
+        let mut index = 0;
+        while client.held > max_held && index + 1 < queue.len() {
+            let transaction_id = queue[index];
+            let mut disputed = transactions
+                .get(transaction_id)
+                .expect("queued disputes are stored")
+                .into_owned();
+            let amount = match disputed.r#type {
+                TransactionType::Deposit { amount }
+                | TransactionType::PendingDeposit { amount } => amount.value(),
+                _ => {
+                    index += 1;
+                    continue;
+                }
+            };
+
+            if let Err(err) = client.release_funds(amount, config.guard_releases) {
+                warn!(
+                    client = client.client,
+                    transaction_id,
+                    ?err,
+                    "dispute cannot be resolved automatically"
+                );
+                index += 1;
+                continue;
+            }
+            disputed.is_disputed = false;
+            disputed.lifecycle.push(DisputeEvent::Resolved);
+            transactions.insert(disputed)?;
+            queue.remove(index);
+            if config.journal {
+                client.record(
+                    transaction_id,
+                    TransactionType::Resolve.name(),
+                    Some(amount),
+                );
+            }
+            info!(
+                client = client.client,
+                transaction_id,
+                %amount,
+                held = %client.held,
+                "held funds exceeded the limit, dispute resolved automatically"
+            );
+            if let Some(hook) = hook {
+                let resolve =
+                    Transaction::new(client.client, transaction_id, TransactionType::Resolve)
+                        .with_currency(client.currency.clone());
+                hook.on_resolve(&resolve, client);
             }
         }
 
         Ok(())
     }
 
+    /// Moves the accounts and transactions of the `other` engine into this one. The engines must
+    /// have processed disjoint sets of clients and transactions, otherwise nothing is merged and
+    /// an error is returned.
+    pub fn merge(&mut self, other: PaymentEngine) -> Result<(), MergeError> {
+        let clients = self
+            .clients
+            .accounts()
+            .map(|account| account.client)
+            .collect::<HashSet<_>>();
+        if let Some(account) = other
+            .clients
+            .accounts()
+            .find(|account| clients.contains(&account.client))
+        {
+            return Err(MergeError::ClientCollision(account.client));
+        }
+        if let Some(transaction) = other
+            .transactions
+            .transactions()
+            .find(|transaction| self.transactions.contains(transaction.id))
+        {
+            return Err(MergeError::TransactionCollision(transaction.id));
+        }
+
+        for account in other.clients.accounts() {
+            let key = (account.client, account.currency.clone());
+            self.clients.get_or_insert_with(key, &|| account.clone());
+        }
+        for transaction in other.transactions.transactions() {
+            self.transactions.insert(transaction.into_owned())?;
+        }
+        self.dispute_queues.extend(other.dispute_queues);
+        self.references.extend(other.references);
+        self.stored_order.extend(other.stored_order);
+        self.forgotten.extend(other.forgotten);
+        self.stored_order
+            .make_contiguous()
+            .sort_unstable_by_key(|&(stored_at, _)| stored_at);
+        self.processed = self.processed.max(other.processed);
+        self.dispute_targets.merge(other.dispute_targets);
+
+        Ok(())
+    }
+
+    /// Number of the stored accounts and transactions with a rough estimate of the memory they
+    /// occupy. Heap allocations owned by the entries (e.g. currency codes) are not included.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let clients = self.clients.accounts().count();
+        let transactions = self.transactions.len();
+
+        MemoryUsage {
+            clients,
+            transactions,
+            estimated_bytes: clients * (size_of::<AccountKey>() + size_of::<AccountStatus>())
+                + transactions * (size_of::<u32>() + size_of::<Transaction>()),
+        }
+    }
+
+    /// Complete state of the engine, for inspection.
+    pub fn state(&self) -> EngineState<'_> {
+        let mut accounts = self.clients.accounts().collect::<Vec<_>>();
+        accounts.sort_by(|a, b| (a.client, &a.currency).cmp(&(b.client, &b.currency)));
+        let mut transactions = self.transactions.transactions().collect::<Vec<_>>();
+        transactions.sort_by_key(|transaction| transaction.id);
+
+        EngineState {
+            accounts,
+            transactions,
+        }
+    }
+
+    pub fn get_transaction(&self, transaction_id: u32) -> Option<Cow<'_, Transaction>> {
+        self.transactions.get(transaction_id)
+    }
+
+    /// Whether the transaction was processed, but then removed by `forget_settled_after`.
+    pub fn is_forgotten(&self, transaction_id: u32) -> bool {
+        self.forgotten.contains(&transaction_id)
+    }
+
+    /// Transactions which are still under dispute, sorted by id.
+    pub fn open_disputes(&self) -> Vec<Cow<'_, Transaction>> {
+        let mut disputes = self
+            .transactions
+            .transactions()
+            .filter(|transaction| transaction.is_disputed)
+            .collect::<Vec<_>>();
+        disputes.sort_by_key(|transaction| transaction.id);
+
+        disputes
+    }
+
+    /// Dispute operations applied to the transaction, in the order of processing.
+    pub fn transaction_lifecycle(&self, transaction_id: u32) -> Option<Vec<DisputeEvent>> {
+        self.transactions
+            .get(transaction_id)
+            .map(|transaction| transaction.into_owned().lifecycle)
+    }
+
+    /// Sets the state of an account before processing, e.g. from the output of a previous run,
+    /// replacing the account of the same client and currency, if any. The seeded account is then
+    /// treated like any other, e.g. a locked one rejects deposits and withdrawals, and as an
+    /// already opened account which has made a deposit. The available and held funds must add up
+    /// to the total funds.
+    pub fn seed_account(&mut self, mut account: AccountStatus) -> Result<(), PaymentEngineError> {
+        if account.available + account.held != account.total {
+            return Err(PaymentEngineError::InconsistentBalances(account.client));
+        }
+        account.is_open = true;
+        account.has_deposited = true;
+        account.peak_total = account.peak_total.max(account.total);
+
+        let key = (account.client, account.currency.clone());
+        let client = account.client;
+        *self
+            .clients
+            .get_or_insert_with(key, &|| AccountStatus::new(client)) = account;
+
+        Ok(())
+    }
+
+    /// Credits the opening `balance` of an account migrated from another system to its available
+    /// and total funds like a deposit, but without recording a transaction which could be
+    /// disputed. The `balance` may be negative.
+    pub fn apply_opening_balance(
+        &mut self,
+        client: u16,
+        balance: Decimal,
+    ) -> Result<(), PaymentEngineError> {
+        let account = self
+            .clients
+            .get_or_insert_with((client, None), &|| AccountStatus::new(client));
+        account.deposit(balance)?;
+
+        Ok(())
+    }
+
+    /// Counts of the dispute attempts by the kind of the referenced transaction, regardless of
+    /// whether the disputes succeeded.
+    pub fn dispute_targets(&self) -> DisputeTargets {
+        self.dispute_targets
+    }
+
+    pub fn get_account(&self, client: u16, currency: Option<&str>) -> Option<&AccountStatus> {
+        self.clients.get(&(client, currency.map(str::to_string)))
+    }
+
     pub fn get_accounts_statuses(&self) -> Vec<AccountStatus> {
-        self.clients.values().cloned().collect()
+        self.clients.accounts().cloned().collect()
     }
 }
 
@@ -132,15 +796,144 @@ pub enum PaymentEngineError {
     NotDisputed(u32),
     #[error("dispute operations can only be applied to the same client account")]
     DisputeForDifferentClient,
+    #[error("release would make the held funds of client {0} negative")]
+    ReleaseExceedsHeld(u16),
+    #[error("transaction (id={0}) was made in a different currency")]
+    CurrencyMismatch(u32),
+    #[error("settle operations can only be applied to the same client account")]
+    SettleForDifferentClient,
+    #[error("transaction (id={0}) is not pending")]
+    NotPending(u32),
+    #[error("client {0} has not made any deposit yet")]
+    WithdrawalBeforeDeposit(u16),
+    #[error("dispute would exceed the maximum held funds of client {0}")]
+    MaxHeldExceeded(u16),
+    #[error("client {0} does not exist")]
+    UnknownClient(u16),
+    #[error("account of client {0} was not opened")]
+    AccountNotOpened(u16),
+    #[error("operation would make the total funds of client {0} negative")]
+    NegativeTotal(u16),
+    #[error("withdrawals from the account of client {0} are frozen")]
+    WithdrawalsFrozen(u16),
+    #[error("no transaction with reference {0} found")]
+    UnknownReference(String),
+    #[error("transaction (id={0}) has a zero amount, there are no funds to dispute")]
+    ZeroAmountDispute(u32),
+    #[error("transaction (id={0}) conflicts with an already processed transaction")]
+    DuplicateTransaction(u32),
+    #[error("failed to store the transaction: {0}")]
+    TransactionStore(#[from] io::Error),
+    #[error("{0} operations are disabled")]
+    OperationDisabled(&'static str),
+    #[error("dispute would make the held funds of client {0} exceed the total funds")]
+    HeldExceedsTotal(u16),
+    #[error("available and held funds of client {0} do not add up to the total funds")]
+    InconsistentBalances(u16),
+    #[error("transaction (id={0}) was rejected by a hook: {1}")]
+    RejectedByHook(u32, String),
+}
+
+impl PaymentEngineError {
+    /// Stable name of the error, e.g. `InsufficientFunds`, for reports and metrics.
+    pub fn code(&self) -> &'static str {
+        match self {
+            PaymentEngineError::InsufficientFunds => "InsufficientFunds",
+            PaymentEngineError::AccountLocked(..) => "AccountLocked",
+            PaymentEngineError::InvalidTransactionType(..) => "InvalidTransactionType",
+            PaymentEngineError::InvalidAmount(..) => "InvalidAmount",
+            PaymentEngineError::TransactionNotFound(..) => "TransactionNotFound",
+            PaymentEngineError::TransactionAlreadyDisputed(..) => "TransactionAlreadyDisputed",
+            PaymentEngineError::NotDisputed(..) => "NotDisputed",
+            PaymentEngineError::DisputeForDifferentClient => "DisputeForDifferentClient",
+            PaymentEngineError::ReleaseExceedsHeld(..) => "ReleaseExceedsHeld",
+            PaymentEngineError::CurrencyMismatch(..) => "CurrencyMismatch",
+            PaymentEngineError::SettleForDifferentClient => "SettleForDifferentClient",
+            PaymentEngineError::NotPending(..) => "NotPending",
+            PaymentEngineError::WithdrawalBeforeDeposit(..) => "WithdrawalBeforeDeposit",
+            PaymentEngineError::MaxHeldExceeded(..) => "MaxHeldExceeded",
+            PaymentEngineError::UnknownClient(..) => "UnknownClient",
+            PaymentEngineError::AccountNotOpened(..) => "AccountNotOpened",
+            PaymentEngineError::NegativeTotal(..) => "NegativeTotal",
+            PaymentEngineError::WithdrawalsFrozen(..) => "WithdrawalsFrozen",
+            PaymentEngineError::UnknownReference(..) => "UnknownReference",
+            PaymentEngineError::ZeroAmountDispute(..) => "ZeroAmountDispute",
+            PaymentEngineError::DuplicateTransaction(..) => "DuplicateTransaction",
+            PaymentEngineError::TransactionStore(..) => "TransactionStore",
+            PaymentEngineError::OperationDisabled(..) => "OperationDisabled",
+            PaymentEngineError::HeldExceedsTotal(..) => "HeldExceedsTotal",
+            PaymentEngineError::InconsistentBalances(..) => "InconsistentBalances",
+            PaymentEngineError::RejectedByHook(..) => "RejectedByHook",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct EngineState<'a> {
+    pub accounts: Vec<&'a AccountStatus>,
+    pub transactions: Vec<Cow<'a, Transaction>>,
+}
+
+/// Counts of the dispute attempts by the kind of the referenced transaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DisputeTargets {
+    /// Disputes of deposits, including the pending ones.
+    pub deposits: u64,
+    pub withdrawals: u64,
+    /// Disputes of transactions which were never processed, were forgotten, or are not
+    /// disputable.
+    pub missing: u64,
+}
+
+impl DisputeTargets {
+    fn record(&mut self, target: Option<&TransactionType>) {
+        match target {
+            Some(TransactionType::Deposit { .. } | TransactionType::PendingDeposit { .. }) => {
+                self.deposits += 1
+            }
+            Some(TransactionType::Withdrawal { .. }) => self.withdrawals += 1,
+            _ => self.missing += 1,
+        }
+    }
+
+    fn merge(&mut self, other: DisputeTargets) {
+        self.deposits += other.deposits;
+        self.withdrawals += other.withdrawals;
+        self.missing += other.missing;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub clients: usize,
+    pub transactions: usize,
+    pub estimated_bytes: usize,
+}
+
+#[derive(Error, Debug)]
+pub enum MergeError {
+    #[error("client {0} is present in both engines")]
+    ClientCollision(u16),
+    #[error("transaction (id={0}) is present in both engines")]
+    TransactionCollision(u32),
+    #[error("failed to store the transaction: {0}")]
+    TransactionStore(#[from] io::Error),
 }
 
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroU64;
+    use std::sync::{Arc, Mutex};
+
     use rust_decimal::dec;
 
     use super::*;
     use crate::payment_engine::TransactionType;
 
+    fn amount(value: Decimal) -> Amount {
+        Amount::try_new(value, Amount::MAX_SCALE).unwrap()
+    }
+
     #[test]
     fn test_deposit_and_withdrawal() {
         let mut engine = PaymentEngine::new();
@@ -148,10 +941,16 @@ mod tests {
             1,
             1,
             TransactionType::Deposit {
-                amount: dec!(100.0),
+                amount: amount(dec!(100.0)),
+            },
+        );
+        let withdrawal = Transaction::new(
+            1,
+            2,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(40.0)),
             },
         );
-        let withdrawal = Transaction::new(1, 2, TransactionType::Withdrawal { amount: dec!(40.0) });
 
         engine.process_transaction(deposit).unwrap();
         engine.process_transaction(withdrawal).unwrap();
@@ -174,7 +973,7 @@ mod tests {
             1,
             1,
             TransactionType::Deposit {
-                amount: dec!(100.0),
+                amount: amount(dec!(100.0)),
             },
         );
         let dispute = Transaction::new(1, 1, TransactionType::Dispute);
@@ -220,12 +1019,18 @@ mod tests {
     #[test]
     fn test_withdrawal_insufficient_funds() {
         let mut engine = PaymentEngine::new();
-        let deposit = Transaction::new(1, 1, TransactionType::Deposit { amount: dec!(50.0) });
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(50.0)),
+            },
+        );
         let withdrawal = Transaction::new(
             1,
             2,
             TransactionType::Withdrawal {
-                amount: dec!(100.0),
+                amount: amount(dec!(100.0)),
             },
         );
 
@@ -248,11 +1053,23 @@ mod tests {
             1,
             1,
             TransactionType::Deposit {
-                amount: dec!(100.0),
+                amount: amount(dec!(100.0)),
+            },
+        );
+        let deposit2 = Transaction::new(
+            1,
+            2,
+            TransactionType::Deposit {
+                amount: amount(dec!(50.0)),
+            },
+        );
+        let withdrawal = Transaction::new(
+            1,
+            3,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(30.0)),
             },
         );
-        let deposit2 = Transaction::new(1, 2, TransactionType::Deposit { amount: dec!(50.0) });
-        let withdrawal = Transaction::new(1, 3, TransactionType::Withdrawal { amount: dec!(30.0) });
 
         engine.process_transaction(deposit1).unwrap();
         engine.process_transaction(deposit2).unwrap();
@@ -315,10 +1132,16 @@ mod tests {
             2,
             1,
             TransactionType::Deposit {
-                amount: dec!(200.0),
+                amount: amount(dec!(200.0)),
+            },
+        );
+        let withdrawal = Transaction::new(
+            2,
+            2,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(50.0)),
             },
         );
-        let withdrawal = Transaction::new(2, 2, TransactionType::Withdrawal { amount: dec!(50.0) });
 
         engine.process_transaction(deposit).unwrap();
         engine.process_transaction(withdrawal).unwrap();
@@ -350,39 +1173,63 @@ mod tests {
     }
 
     #[test]
-    fn test_negative_deposit_and_withdrawal() {
-        let mut engine = PaymentEngine::new();
-        let deposit = Transaction::new(
-            1,
-            1,
-            TransactionType::Deposit {
-                amount: dec!(-10.0),
-            },
-        );
-        let withdrawal = Transaction::new(
-            1,
-            2,
-            TransactionType::Withdrawal {
-                amount: dec!(-20.0),
-            },
-        );
+    fn test_dispute_and_resolve_withdrawal_is_noop() {
+        for guard_releases in [false, true] {
+            let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+                guard_releases,
+                ..Default::default()
+            });
+            engine
+                .process_transaction(Transaction::new(
+                    1,
+                    1,
+                    TransactionType::Deposit {
+                        amount: amount(dec!(100.0)),
+                    },
+                ))
+                .unwrap();
+            engine
+                .process_transaction(Transaction::new(
+                    1,
+                    2,
+                    TransactionType::Withdrawal {
+                        amount: amount(dec!(40.1234)),
+                    },
+                ))
+                .unwrap();
+            let balances = |engine: &PaymentEngine| {
+                let account = engine.get_account(1, None).unwrap();
+                (account.available, account.held, account.total)
+            };
+            let before_dispute = balances(&engine);
 
-        let result_deposit = engine.process_transaction(deposit);
-        assert!(matches!(
-            result_deposit,
-            Err(PaymentEngineError::InvalidAmount(_, _))
-        ));
+            engine
+                .process_transaction(Transaction::new(1, 2, TransactionType::Dispute))
+                .unwrap();
+            assert_eq!(
+                balances(&engine),
+                (dec!(100.0), dec!(-40.1234), dec!(59.8766))
+            );
 
-        let result_withdrawal = engine.process_transaction(withdrawal);
-        assert!(matches!(
-            result_withdrawal,
-            Err(PaymentEngineError::InvalidAmount(_, _))
-        ));
+            engine
+                .process_transaction(Transaction::new(1, 2, TransactionType::Resolve))
+                .unwrap();
+            assert_eq!(balances(&engine), before_dispute);
+            assert!(!engine.get_transaction(2).unwrap().is_disputed);
+        }
     }
 
     #[test]
     fn test_dispute_nonexistent_transaction() {
         let mut engine = PaymentEngine::new();
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(100.0)),
+            },
+        );
+        engine.process_transaction(deposit).unwrap();
         let dispute = Transaction::new(1, 99, TransactionType::Dispute);
 
         let result = engine.process_transaction(dispute);
@@ -398,7 +1245,17 @@ mod tests {
             panic!("account should exist")
         };
 
-        assert_eq!(account.available, dec!(0.0));
+        assert_eq!(account.available, dec!(100.0));
+    }
+
+    #[test]
+    fn test_dispute_for_unknown_client() {
+        let mut engine = PaymentEngine::new();
+        let dispute = Transaction::new(1, 99, TransactionType::Dispute);
+
+        let result = engine.process_transaction(dispute);
+        assert!(matches!(result, Err(PaymentEngineError::UnknownClient(1))));
+        assert!(engine.get_accounts_statuses().is_empty());
     }
 
     #[test]
@@ -408,16 +1265,1955 @@ mod tests {
             1,
             1,
             TransactionType::Deposit {
-                amount: dec!(100.0),
+                amount: amount(dec!(100.0)),
             },
         );
         engine.process_transaction(deposit).unwrap();
-
-        let dispute = Transaction::new(2, 1, TransactionType::Dispute);
-        let result = engine.process_transaction(dispute);
-        assert!(matches!(
-            result,
+        let deposit = Transaction::new(
+            2,
+            2,
+            TransactionType::Deposit {
+                amount: amount(dec!(100.0)),
+            },
+        );
+        engine.process_transaction(deposit).unwrap();
+
+        let dispute = Transaction::new(2, 1, TransactionType::Dispute);
+        let result = engine.process_transaction(dispute);
+        assert!(matches!(
+            result,
             Err(PaymentEngineError::DisputeForDifferentClient)
         ));
     }
+
+    #[test]
+    fn test_withdrawal_within_epsilon() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            withdrawal_epsilon: dec!(0.0001),
+            ..Default::default()
+        });
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(100.0000)),
+            },
+        );
+        let withdrawal = Transaction::new(
+            1,
+            2,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(100.0001)),
+            },
+        );
+
+        engine.process_transaction(deposit).unwrap();
+        engine.process_transaction(withdrawal).unwrap();
+        let account = engine
+            .get_accounts_statuses()
+            .into_iter()
+            .find(|a| a.client == 1)
+            .unwrap();
+        assert_eq!(account.available, dec!(0.0));
+        assert_eq!(account.total, dec!(0.0));
+    }
+
+    #[test]
+    fn test_chargeback_withdrawal_within_epsilon() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            withdrawal_epsilon: dec!(0.0001),
+            ..Default::default()
+        });
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(100.0000)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(100.0001)),
+                },
+            ),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            Transaction::new(1, 2, TransactionType::Chargeback),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.total, dec!(100.0000));
+        assert_eq!(account.available, dec!(100.0000));
+        assert!(account.held.is_zero());
+        assert_eq!(account.gross_withdrawals, dec!(100.0000));
+    }
+
+    #[test]
+    fn test_withdrawal_beyond_epsilon() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            withdrawal_epsilon: dec!(0.0001),
+            ..Default::default()
+        });
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(100.0000)),
+            },
+        );
+        let withdrawal = Transaction::new(
+            1,
+            2,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(100.0002)),
+            },
+        );
+
+        engine.process_transaction(deposit).unwrap();
+        let result = engine.process_transaction(withdrawal);
+        assert!(matches!(result, Err(PaymentEngineError::InsufficientFunds)));
+        let account = engine
+            .get_accounts_statuses()
+            .into_iter()
+            .find(|a| a.client == 1)
+            .unwrap();
+        assert_eq!(account.available, dec!(100.0));
+        assert_eq!(account.total, dec!(100.0));
+    }
+
+    #[test]
+    fn test_deny_withdrawal_before_deposit() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            deny_withdrawal_before_deposit: true,
+            ..Default::default()
+        });
+        let withdrawal = Transaction::new(
+            1,
+            1,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(0.0)),
+            },
+        );
+        let deposit = Transaction::new(
+            1,
+            2,
+            TransactionType::Deposit {
+                amount: amount(dec!(10.0)),
+            },
+        );
+        let second_withdrawal = Transaction::new(
+            1,
+            3,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(5.0)),
+            },
+        );
+
+        let result = engine.process_transaction(withdrawal);
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::WithdrawalBeforeDeposit(1))
+        ));
+
+        engine.process_transaction(deposit).unwrap();
+        engine.process_transaction(second_withdrawal).unwrap();
+        let account = engine
+            .get_accounts_statuses()
+            .into_iter()
+            .find(|a| a.client == 1)
+            .unwrap();
+        assert_eq!(account.available, dec!(5.0));
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct RecordingHook {
+        calls: Arc<Mutex<Vec<(&'static str, u32)>>>,
+    }
+
+    impl TransactionHook for RecordingHook {
+        fn on_deposit(&mut self, transaction: &Transaction, _account: &AccountStatus) {
+            self.calls.lock().unwrap().push(("deposit", transaction.id));
+        }
+
+        fn on_withdrawal(&mut self, transaction: &Transaction, _account: &AccountStatus) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(("withdrawal", transaction.id));
+        }
+
+        fn on_dispute(&mut self, transaction: &Transaction, _account: &AccountStatus) {
+            self.calls.lock().unwrap().push(("dispute", transaction.id));
+        }
+
+        fn on_resolve(&mut self, transaction: &Transaction, _account: &AccountStatus) {
+            self.calls.lock().unwrap().push(("resolve", transaction.id));
+        }
+
+        fn on_chargeback(&mut self, transaction: &Transaction, _account: &AccountStatus) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(("chargeback", transaction.id));
+        }
+    }
+
+    #[test]
+    fn test_transaction_hook() {
+        let hook = RecordingHook::default();
+        let mut engine = PaymentEngine::new();
+        engine.set_hook(hook.clone());
+
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(50.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(10.0)),
+                },
+            ),
+            // fails due to insufficient funds, so the hook must not be called
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(99.0)),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Resolve),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            Transaction::new(1, 2, TransactionType::Chargeback),
+        ];
+        for transaction in transactions {
+            let _ = engine.process_transaction(transaction);
+        }
+
+        assert_eq!(
+            *hook.calls.lock().unwrap(),
+            vec![
+                ("deposit", 1),
+                ("withdrawal", 2),
+                ("dispute", 1),
+                ("resolve", 1),
+                ("dispute", 2),
+                ("chargeback", 2),
+            ]
+        );
+    }
+
+    #[derive(Debug, Default, Clone)]
+    struct LimitHook {
+        withdrawals: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl TransactionHook for LimitHook {
+        fn before_transaction(
+            &mut self,
+            transaction: &Transaction,
+            account: Option<&AccountStatus>,
+        ) -> Result<(), String> {
+            match (&transaction.r#type, account) {
+                (TransactionType::Withdrawal { amount }, Some(account))
+                    if amount.value() * dec!(2) > account.total =>
+                {
+                    Err("withdrawal of more than half of the funds".to_string())
+                }
+                _ => Ok(()),
+            }
+        }
+
+        fn on_withdrawal(&mut self, transaction: &Transaction, _account: &AccountStatus) {
+            self.withdrawals.lock().unwrap().push(transaction.id);
+        }
+    }
+
+    #[test]
+    fn test_before_transaction_hook() {
+        let hook = LimitHook::default();
+        let mut engine = PaymentEngine::new();
+        engine.set_hook(hook.clone());
+        engine
+            .process_transaction(Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(100.0)),
+                },
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(Transaction::new(
+            1,
+            2,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(60.0)),
+            },
+        ));
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::RejectedByHook(2, _))
+        ));
+        engine
+            .process_transaction(Transaction::new(
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(40.0)),
+                },
+            ))
+            .unwrap();
+
+        assert_eq!(*hook.withdrawals.lock().unwrap(), vec![3]);
+        assert!(engine.get_transaction(2).is_none());
+        assert_eq!(engine.get_account(1, None).unwrap().total, dec!(60.0));
+    }
+
+    #[test]
+    fn test_max_held() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            max_held: Some(dec!(100.0)),
+            ..Default::default()
+        });
+        for (id, value) in [(1, dec!(60.0)), (2, dec!(40.0)), (3, dec!(0.01))] {
+            let amount = amount(value);
+            engine
+                .process_transaction(Transaction::new(1, id, TransactionType::Deposit { amount }))
+                .unwrap();
+        }
+
+        engine
+            .process_transaction(Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::new(1, 2, TransactionType::Dispute))
+            .unwrap();
+        let result = engine.process_transaction(Transaction::new(1, 3, TransactionType::Dispute));
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::MaxHeldExceeded(1))
+        ));
+
+        assert!(!engine.get_transaction(3).unwrap().is_disputed);
+        let account = engine
+            .get_accounts_statuses()
+            .into_iter()
+            .find(|a| a.client == 1)
+            .unwrap();
+        assert_eq!(account.held, dec!(100.0));
+        assert_eq!(account.available, dec!(0.01));
+    }
+
+    #[test]
+    fn test_from_records() {
+        let (engine, failures) = PaymentEngine::from_records(vec![
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(50.0)),
+                },
+            ),
+            Transaction::new(
+                2,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(20.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(80.0)),
+                },
+            ),
+            Transaction::new(2, 2, TransactionType::Dispute),
+            Transaction::new(2, 4, TransactionType::Resolve),
+        ]);
+
+        let mut accounts = engine.get_accounts_statuses();
+        accounts.sort_by_key(|a| a.client);
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].available, dec!(50.0));
+        assert_eq!(accounts[1].available, dec!(0.0));
+        assert_eq!(accounts[1].held, dec!(20.0));
+
+        assert_eq!(failures.len(), 2);
+        assert!(matches!(
+            failures[0],
+            (3, PaymentEngineError::InsufficientFunds)
+        ));
+        assert!(matches!(
+            failures[1],
+            (4, PaymentEngineError::TransactionNotFound(4))
+        ));
+    }
+
+    #[test]
+    fn test_pending_deposit_and_settle() {
+        let mut engine = PaymentEngine::new();
+        let pending = Transaction::new(
+            1,
+            1,
+            TransactionType::PendingDeposit {
+                amount: amount(dec!(100.0)),
+            },
+        );
+        engine.process_transaction(pending).unwrap();
+
+        let account = engine
+            .get_accounts_statuses()
+            .into_iter()
+            .find(|a| a.client == 1)
+            .unwrap();
+        assert_eq!(account.available, dec!(0.0));
+        assert_eq!(account.held, dec!(100.0));
+        assert_eq!(account.total, dec!(100.0));
+
+        engine
+            .process_transaction(Transaction::new(1, 1, TransactionType::Settle))
+            .unwrap();
+        let account = engine
+            .get_accounts_statuses()
+            .into_iter()
+            .find(|a| a.client == 1)
+            .unwrap();
+        assert_eq!(account.available, dec!(100.0));
+        assert_eq!(account.held, dec!(0.0));
+        assert_eq!(account.total, dec!(100.0));
+
+        let result = engine.process_transaction(Transaction::new(1, 1, TransactionType::Settle));
+        assert!(matches!(result, Err(PaymentEngineError::NotPending(1))));
+    }
+
+    #[test]
+    fn test_withdrawal_before_settle() {
+        let mut engine = PaymentEngine::new();
+        let pending = Transaction::new(
+            1,
+            1,
+            TransactionType::PendingDeposit {
+                amount: amount(dec!(100.0)),
+            },
+        );
+        let withdrawal = Transaction::new(
+            1,
+            2,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(50.0)),
+            },
+        );
+
+        engine.process_transaction(pending).unwrap();
+        let result = engine.process_transaction(withdrawal);
+        assert!(matches!(result, Err(PaymentEngineError::InsufficientFunds)));
+    }
+
+    #[test]
+    fn test_disputes_on_locked() {
+        for (disputes_on_locked, expect_locked_error) in [
+            (DisputesOnLocked::Allow, false),
+            (DisputesOnLocked::Deny, true),
+        ] {
+            let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+                disputes_on_locked,
+                ..Default::default()
+            });
+            let transactions = [
+                Transaction::new(
+                    1,
+                    1,
+                    TransactionType::Deposit {
+                        amount: amount(dec!(10.0)),
+                    },
+                ),
+                Transaction::new(
+                    1,
+                    2,
+                    TransactionType::Deposit {
+                        amount: amount(dec!(20.0)),
+                    },
+                ),
+                Transaction::new(1, 1, TransactionType::Dispute),
+                Transaction::new(1, 1, TransactionType::Chargeback),
+            ];
+            for transaction in transactions {
+                engine.process_transaction(transaction).unwrap();
+            }
+
+            let result =
+                engine.process_transaction(Transaction::new(1, 2, TransactionType::Dispute));
+            assert_eq!(
+                matches!(result, Err(PaymentEngineError::AccountLocked(1))),
+                expect_locked_error
+            );
+            assert_eq!(result.is_ok(), !expect_locked_error);
+        }
+    }
+
+    #[test]
+    fn test_peak_total() {
+        let mut engine = PaymentEngine::new();
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(50.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(150.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(150.0)),
+                },
+            ),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+
+        let account = engine
+            .get_accounts_statuses()
+            .into_iter()
+            .find(|a| a.client == 1)
+            .unwrap();
+        assert_eq!(account.total, dec!(50.0));
+        assert_eq!(account.peak_total, dec!(200.0));
+    }
+
+    #[test]
+    fn test_currency_sub_accounts() {
+        let usd = Some("USD".to_string());
+        let eur = Some("EUR".to_string());
+        let mut engine = PaymentEngine::new();
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(100.0)),
+                },
+            )
+            .with_currency(usd.clone()),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(50.0)),
+                },
+            )
+            .with_currency(eur.clone()),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(30.0)),
+                },
+            )
+            .with_currency(usd.clone()),
+            Transaction::new(1, 2, TransactionType::Dispute).with_currency(eur.clone()),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+
+        // the deposit was made in USD, so it can't be disputed as EUR
+        let result = engine.process_transaction(
+            Transaction::new(1, 1, TransactionType::Dispute).with_currency(eur.clone()),
+        );
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::CurrencyMismatch(1))
+        ));
+        // USD funds are insufficient, even though the client has enough funds in total
+        let result = engine.process_transaction(
+            Transaction::new(
+                1,
+                4,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(80.0)),
+                },
+            )
+            .with_currency(usd.clone()),
+        );
+        assert!(matches!(result, Err(PaymentEngineError::InsufficientFunds)));
+
+        let accounts = engine.get_accounts_statuses();
+        assert_eq!(accounts.len(), 2);
+        let usd_account = accounts.iter().find(|a| a.currency == usd).unwrap();
+        assert_eq!(usd_account.client, 1);
+        assert_eq!(usd_account.available, dec!(70.0));
+        assert_eq!(usd_account.held, dec!(0.0));
+        let eur_account = accounts.iter().find(|a| a.currency == eur).unwrap();
+        assert_eq!(eur_account.client, 1);
+        assert_eq!(eur_account.available, dec!(0.0));
+        assert_eq!(eur_account.held, dec!(50.0));
+    }
+
+    #[test]
+    fn test_guard_releases() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            guard_releases: true,
+            ..Default::default()
+        });
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(100.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(60.0)),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 2, TransactionType::Dispute),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+
+        // held is 100 - 60 = 40, so releasing the 100 of the deposit would make it negative
+        let result = engine.process_transaction(Transaction::new(1, 1, TransactionType::Resolve));
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::ReleaseExceedsHeld(1))
+        ));
+        assert!(engine.get_transaction(1).unwrap().is_disputed);
+        let account = engine
+            .get_accounts_statuses()
+            .into_iter()
+            .find(|a| a.client == 1)
+            .unwrap();
+        assert_eq!(account.held, dec!(40.0));
+        assert_eq!(account.available, dec!(0.0));
+    }
+
+    /// Records the balances of the account as observed by the hooks, i.e. while the transaction
+    /// is still being processed.
+    #[derive(Debug, Clone, Default)]
+    struct BalancesHook {
+        balances: Arc<Mutex<Vec<(Decimal, Decimal, Decimal)>>>,
+        peak_totals: Arc<Mutex<Vec<Decimal>>>,
+    }
+
+    impl BalancesHook {
+        fn record(&self, account: &AccountStatus) {
+            self.balances
+                .lock()
+                .unwrap()
+                .push((account.available, account.held, account.total));
+            self.peak_totals.lock().unwrap().push(account.peak_total);
+        }
+    }
+
+    impl TransactionHook for BalancesHook {
+        fn on_deposit(&mut self, _transaction: &Transaction, account: &AccountStatus) {
+            self.record(account);
+        }
+
+        fn on_withdrawal(&mut self, _transaction: &Transaction, account: &AccountStatus) {
+            self.record(account);
+        }
+
+        fn on_dispute(&mut self, _transaction: &Transaction, account: &AccountStatus) {
+            self.record(account);
+        }
+
+        fn on_resolve(&mut self, _transaction: &Transaction, account: &AccountStatus) {
+            self.record(account);
+        }
+    }
+
+    #[test]
+    fn test_no_transient_balances() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            guard_releases: true,
+            ..Default::default()
+        });
+        let hook = BalancesHook::default();
+        engine.set_hook(hook.clone());
+
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(100.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(20.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(90.0)),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 3, TransactionType::Dispute),
+            // rejected, releasing the 100 would make the held funds of 10 negative
+            Transaction::new(1, 1, TransactionType::Resolve),
+            // rejected, insufficient funds
+            Transaction::new(
+                1,
+                4,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(200.0)),
+                },
+            ),
+            Transaction::new(1, 3, TransactionType::Resolve),
+            Transaction::new(1, 1, TransactionType::Resolve),
+        ];
+        let results = transactions
+            .into_iter()
+            .map(|transaction| engine.process_transaction(transaction).is_ok())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            [true, true, true, true, true, false, false, true, true]
+        );
+
+        let balances = hook.balances.lock().unwrap();
+        let peak_totals = hook.peak_totals.lock().unwrap();
+        assert_eq!(balances.len(), 7);
+        for (&(available, held, total), &peak_total) in balances.iter().zip(peak_totals.iter()) {
+            assert_eq!(available + held, total);
+            assert!(held >= Decimal::ZERO);
+            // the derived balances are updated together with the others
+            assert!(peak_total >= total);
+        }
+        assert_eq!(peak_totals[..2], [dec!(100.0), dec!(120.0)]);
+        // the rejected operations left no trace between the observed states
+        assert_eq!(
+            balances[4..],
+            [
+                (dec!(20.0), dec!(10.0), dec!(30.0)),
+                (dec!(-70.0), dec!(100.0), dec!(30.0)),
+                (dec!(30.0), dec!(0.0), dec!(30.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_idempotent_retries() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            idempotent_retries: true,
+            ..Default::default()
+        });
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(100.0)),
+            },
+        );
+        engine.process_transaction(deposit.clone()).unwrap();
+        engine.process_transaction(deposit).unwrap();
+
+        let accounts = engine.get_accounts_statuses();
+        assert_eq!(accounts[0].available, dec!(100.0));
+        assert_eq!(accounts[0].total, dec!(100.0));
+    }
+
+    #[test]
+    fn test_idempotent_retries_conflicting_reuse() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            idempotent_retries: true,
+            ..Default::default()
+        });
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(100.0)),
+            },
+        );
+        engine.process_transaction(deposit).unwrap();
+
+        let conflicting = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(50.0)),
+            },
+        );
+        let result = engine.process_transaction(conflicting);
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::DuplicateTransaction(1))
+        ));
+
+        let withdrawal = Transaction::new(
+            1,
+            1,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(100.0)),
+            },
+        );
+        let result = engine.process_transaction(withdrawal);
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::DuplicateTransaction(1))
+        ));
+
+        let accounts = engine.get_accounts_statuses();
+        assert_eq!(accounts[0].available, dec!(100.0));
+    }
+
+    #[test]
+    fn test_client_stores_are_equivalent() {
+        let transactions = [
+            Transaction::new(
+                3,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(5.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(7.5)),
+                },
+            ),
+            Transaction::new(
+                2,
+                3,
+                TransactionType::Deposit {
+                    amount: amount(dec!(1.0)),
+                },
+            )
+            .with_currency(Some("EUR".to_string())),
+            Transaction::new(
+                1,
+                4,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(2.5)),
+                },
+            ),
+            Transaction::new(3, 1, TransactionType::Dispute),
+            Transaction::new(3, 1, TransactionType::Chargeback),
+            Transaction::new(
+                2,
+                5,
+                TransactionType::Deposit {
+                    amount: amount(dec!(3.0)),
+                },
+            ),
+            Transaction::new(2, 3, TransactionType::Dispute).with_currency(Some("EUR".to_string())),
+            Transaction::new(4, 99, TransactionType::Dispute),
+        ];
+
+        let outputs = [ClientStoreKind::Hashmap, ClientStoreKind::Vec].map(|client_store| {
+            let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+                client_store,
+                ..Default::default()
+            });
+            let results = transactions
+                .iter()
+                .map(|t| engine.process_transaction(t.clone()).is_ok())
+                .collect::<Vec<_>>();
+            let mut accounts = engine.get_accounts_statuses();
+            accounts.sort_by(|a, b| (a.client, &a.currency).cmp(&(b.client, &b.currency)));
+            format!("{results:?} {accounts:?}")
+        });
+
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    #[test]
+    fn test_transaction_lifecycle() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            disputes_on_locked: DisputesOnLocked::Allow,
+            ..Default::default()
+        });
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(100.0)),
+            },
+        );
+        engine.process_transaction(deposit).unwrap();
+        assert_eq!(engine.transaction_lifecycle(1), Some(vec![]));
+
+        for r#type in [
+            TransactionType::Dispute,
+            TransactionType::Resolve,
+            TransactionType::Dispute,
+            TransactionType::Chargeback,
+        ] {
+            engine
+                .process_transaction(Transaction::new(1, 1, r#type))
+                .unwrap();
+        }
+        // failed operations are not recorded
+        engine
+            .process_transaction(Transaction::new(1, 1, TransactionType::Resolve))
+            .unwrap_err();
+
+        assert_eq!(
+            engine.transaction_lifecycle(1),
+            Some(vec![
+                DisputeEvent::Disputed,
+                DisputeEvent::Resolved,
+                DisputeEvent::Disputed,
+                DisputeEvent::ChargedBack,
+            ])
+        );
+        assert_eq!(engine.transaction_lifecycle(2), None);
+    }
+
+    #[test]
+    fn test_on_undisputed_ignore() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            on_undisputed: OnUndisputed::Ignore,
+            ..Default::default()
+        });
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(100.0)),
+            },
+        );
+        engine.process_transaction(deposit).unwrap();
+
+        engine
+            .process_transaction(Transaction::new(1, 1, TransactionType::Resolve))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::new(1, 1, TransactionType::Chargeback))
+            .unwrap();
+
+        let accounts = engine.get_accounts_statuses();
+        assert_eq!(accounts[0].available, dec!(100.0));
+        assert!(!accounts[0].locked);
+    }
+
+    #[test]
+    fn test_merge() {
+        let (mut engine, _) = PaymentEngine::from_records([
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(2.5)),
+                },
+            ),
+        ]);
+        let (other, _) = PaymentEngine::from_records([
+            Transaction::new(
+                2,
+                3,
+                TransactionType::Deposit {
+                    amount: amount(dec!(5.0)),
+                },
+            ),
+            Transaction::new(2, 3, TransactionType::Dispute),
+        ]);
+
+        engine.merge(other).unwrap();
+
+        let mut accounts = engine.get_accounts_statuses();
+        accounts.sort_by_key(|a| a.client);
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].available, dec!(7.5));
+        assert_eq!(accounts[1].held, dec!(5.0));
+        assert!(engine.get_transaction(3).is_some());
+
+        // the merged transactions can still be disputed
+        engine
+            .process_transaction(Transaction::new(2, 3, TransactionType::Resolve))
+            .unwrap();
+        assert_eq!(engine.get_account(2, None).unwrap().available, dec!(5.0));
+    }
+
+    #[test]
+    fn test_merge_collisions() {
+        let (mut engine, _) = PaymentEngine::from_records([Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(10.0)),
+            },
+        )]);
+
+        let (other, _) = PaymentEngine::from_records([Transaction::new(
+            1,
+            2,
+            TransactionType::Deposit {
+                amount: amount(dec!(5.0)),
+            },
+        )]);
+        assert!(matches!(
+            engine.merge(other),
+            Err(MergeError::ClientCollision(1))
+        ));
+
+        let (other, _) = PaymentEngine::from_records([Transaction::new(
+            2,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(5.0)),
+            },
+        )]);
+        assert!(matches!(
+            engine.merge(other),
+            Err(MergeError::TransactionCollision(1))
+        ));
+
+        let accounts = engine.get_accounts_statuses();
+        assert_eq!(accounts.len(), 1);
+        assert_eq!(accounts[0].total, dec!(10.0));
+    }
+
+    #[test]
+    fn test_treat_locked_as_readonly() {
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(5.0)),
+                },
+            ),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            Transaction::new(1, 2, TransactionType::Chargeback),
+        ];
+        let deposit = Transaction::new(
+            1,
+            3,
+            TransactionType::Deposit {
+                amount: amount(dec!(1.0)),
+            },
+        );
+
+        let mut engine = PaymentEngine::new();
+        for transaction in transactions.clone() {
+            engine.process_transaction(transaction).unwrap();
+        }
+        assert!(matches!(
+            engine.process_transaction(deposit.clone()),
+            Err(PaymentEngineError::AccountLocked(1))
+        ));
+
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            treat_locked_as_readonly: true,
+            ..Default::default()
+        });
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+        engine.process_transaction(deposit).unwrap();
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.total, dec!(10.0));
+        assert!(engine.get_transaction(3).is_none());
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let (engine, failures) = PaymentEngine::from_records([
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            ),
+            Transaction::new(
+                2,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(5.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(1.0)),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(
+                3,
+                4,
+                TransactionType::Deposit {
+                    amount: amount(dec!(1.0)),
+                },
+            )
+            .with_currency(Some("EUR".to_string())),
+        ]);
+        assert!(failures.is_empty());
+
+        let usage = engine.memory_usage();
+        assert_eq!(usage.clients, 3);
+        assert_eq!(usage.transactions, 4);
+        assert_eq!(
+            usage.estimated_bytes,
+            3 * (size_of::<AccountKey>() + size_of::<AccountStatus>())
+                + 4 * (size_of::<u32>() + size_of::<Transaction>())
+        );
+    }
+
+    #[test]
+    fn test_require_explicit_open() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            require_explicit_open: true,
+            ..Default::default()
+        });
+        let deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(10.0)),
+            },
+        );
+        let result = engine.process_transaction(deposit.clone());
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::AccountNotOpened(1))
+        ));
+        let withdrawal = Transaction::new(
+            1,
+            2,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(1.0)),
+            },
+        );
+        let result = engine.process_transaction(withdrawal.clone());
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::AccountNotOpened(1))
+        ));
+        assert!(engine.get_account(1, None).is_none());
+
+        engine
+            .process_transaction(Transaction::new(1, 0, TransactionType::OpenAccount))
+            .unwrap();
+        engine.process_transaction(deposit).unwrap();
+        engine.process_transaction(withdrawal).unwrap();
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(9.0));
+    }
+
+    #[test]
+    fn test_seed_account() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            require_explicit_open: true,
+            deny_withdrawal_before_deposit: true,
+            ..Default::default()
+        });
+        assert!(matches!(
+            engine.seed_account(AccountStatus {
+                available: dec!(5.0),
+                held: dec!(1.0),
+                total: dec!(5.0),
+                ..AccountStatus::new(1)
+            }),
+            Err(PaymentEngineError::InconsistentBalances(1))
+        ));
+        assert!(engine.get_account(1, None).is_none());
+
+        engine
+            .seed_account(AccountStatus {
+                available: dec!(5.0),
+                held: dec!(1.0),
+                total: dec!(6.0),
+                ..AccountStatus::new(1)
+            })
+            .unwrap();
+        // the seeded account was opened and funded by a previous run
+        engine
+            .process_transaction(Transaction::new(
+                1,
+                1,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(2.0)),
+                },
+            ))
+            .unwrap();
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(3.0));
+        assert_eq!(account.total, dec!(4.0));
+        assert_eq!(account.peak_total, dec!(6.0));
+    }
+
+    #[test]
+    fn test_freeze_withdrawals() {
+        let mut engine = PaymentEngine::new();
+        let deposit = |id| {
+            Transaction::new(
+                1,
+                id,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            )
+        };
+        let withdrawal = |id| {
+            Transaction::new(
+                1,
+                id,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(3.0)),
+                },
+            )
+        };
+
+        engine.process_transaction(deposit(1)).unwrap();
+        engine
+            .process_transaction(Transaction::new(1, 2, TransactionType::FreezeWithdrawals))
+            .unwrap();
+        engine.process_transaction(deposit(3)).unwrap();
+        let result = engine.process_transaction(withdrawal(4));
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::WithdrawalsFrozen(1))
+        ));
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(20.0));
+        assert!(account.withdrawals_frozen);
+        assert!(!account.locked);
+
+        engine
+            .process_transaction(Transaction::new(1, 5, TransactionType::UnfreezeWithdrawals))
+            .unwrap();
+        engine.process_transaction(withdrawal(6)).unwrap();
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(17.0));
+        assert!(!account.withdrawals_frozen);
+    }
+
+    #[test]
+    fn test_no_negative_total() {
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(100.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(80.0)),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+        ];
+        let chargeback = Transaction::new(1, 1, TransactionType::Chargeback);
+
+        let (mut engine, _) = PaymentEngine::from_records(transactions.clone());
+        engine.process_transaction(chargeback.clone()).unwrap();
+        assert_eq!(engine.get_account(1, None).unwrap().total, dec!(-80.0));
+
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            no_negative_total: true,
+            ..Default::default()
+        });
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+        let result = engine.process_transaction(chargeback);
+        assert!(matches!(result, Err(PaymentEngineError::NegativeTotal(1))));
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(-80.0));
+        assert_eq!(account.held, dec!(100.0));
+        assert_eq!(account.total, dec!(20.0));
+        assert!(!account.locked);
+        assert!(engine.get_transaction(1).unwrap().is_disputed);
+    }
+
+    #[test]
+    fn test_no_negative_total_withdrawal() {
+        // the dispute provisionally returns the withdrawn funds to the available ones, but not to
+        // the total
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(100.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(100.0)),
+                },
+            ),
+            Transaction::new(1, 2, TransactionType::Dispute),
+        ];
+        let withdrawal = Transaction::new(
+            1,
+            3,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(50.0)),
+            },
+        );
+
+        let (mut engine, _) = PaymentEngine::from_records(transactions.clone());
+        engine.process_transaction(withdrawal.clone()).unwrap();
+        assert_eq!(engine.get_account(1, None).unwrap().total, dec!(-50.0));
+
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            no_negative_total: true,
+            ..Default::default()
+        });
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+        let result = engine.process_transaction(withdrawal);
+        assert!(matches!(result, Err(PaymentEngineError::NegativeTotal(1))));
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(100.0));
+        assert_eq!(account.held, dec!(-100.0));
+        assert_eq!(account.total, dec!(0));
+        assert!(engine.get_transaction(3).is_none());
+    }
+
+    #[test]
+    fn test_reject_zero_amount_disputes() {
+        let zero_deposit = Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(0.0)),
+            },
+        );
+
+        // a no-op by default
+        let mut engine = PaymentEngine::new();
+        engine.process_transaction(zero_deposit.clone()).unwrap();
+        engine
+            .process_transaction(Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(0.0));
+        assert_eq!(account.held, dec!(0.0));
+        assert!(engine.get_transaction(1).unwrap().is_disputed);
+
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            reject_zero_amount_disputes: true,
+            ..Default::default()
+        });
+        engine.process_transaction(zero_deposit).unwrap();
+        let result = engine.process_transaction(Transaction::new(1, 1, TransactionType::Dispute));
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::ZeroAmountDispute(1))
+        ));
+        assert!(!engine.get_transaction(1).unwrap().is_disputed);
+    }
+
+    #[test]
+    fn test_forget_settled_after() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            forget_settled_after: NonZeroU64::new(2),
+            ..Default::default()
+        });
+        let deposit = |id| {
+            Transaction::new(
+                1,
+                id,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            )
+        };
+
+        engine.process_transaction(deposit(1)).unwrap();
+        engine.process_transaction(deposit(2)).unwrap();
+        engine
+            .process_transaction(Transaction::new(1, 2, TransactionType::Dispute))
+            .unwrap();
+        engine.process_transaction(deposit(3)).unwrap();
+
+        // the first deposit is old enough to be forgotten, the disputed one is kept
+        assert!(engine.get_transaction(1).is_none());
+        assert!(engine.is_forgotten(1));
+        assert!(engine.get_transaction(2).is_some());
+        assert!(!engine.is_forgotten(2));
+        let result = engine.process_transaction(Transaction::new(1, 1, TransactionType::Dispute));
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::TransactionNotFound(1))
+        ));
+        engine
+            .process_transaction(Transaction::new(1, 2, TransactionType::Resolve))
+            .unwrap();
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(30.0));
+        assert_eq!(account.held, dec!(0.0));
+    }
+
+    #[test]
+    fn test_dispute_by_ref() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            dispute_by_ref: true,
+            ..Default::default()
+        });
+        let deposit = |id, value, reference: &str| {
+            Transaction::new(
+                1,
+                id,
+                TransactionType::Deposit {
+                    amount: amount(value),
+                },
+            )
+            .with_reference(Some(reference.to_string()))
+        };
+        let dispute = |id, reference: Option<&str>| {
+            Transaction::new(1, id, TransactionType::Dispute)
+                .with_reference(reference.map(str::to_string))
+        };
+
+        engine
+            .process_transaction(deposit(1, dec!(10.0), "abc"))
+            .unwrap();
+        engine
+            .process_transaction(deposit(2, dec!(4.0), "def"))
+            .unwrap();
+
+        // the `tx` of the dispute is ignored in favor of the reference
+        engine
+            .process_transaction(dispute(99, Some("def")))
+            .unwrap();
+        assert!(engine.get_transaction(2).unwrap().is_disputed);
+        assert!(!engine.get_transaction(1).unwrap().is_disputed);
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(10.0));
+        assert_eq!(account.held, dec!(4.0));
+
+        let result = engine.process_transaction(dispute(1, Some("xyz")));
+        assert!(
+            matches!(result, Err(PaymentEngineError::UnknownReference(reference)) if reference == "xyz")
+        );
+
+        // disputes without a reference are matched by `tx`
+        engine.process_transaction(dispute(1, None)).unwrap();
+        assert!(engine.get_transaction(1).unwrap().is_disputed);
+    }
+
+    #[test]
+    fn test_clamp_chargeback_residue() {
+        for clamp_chargeback_residue in [false, true] {
+            let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+                clamp_chargeback_residue,
+                ..Default::default()
+            });
+            // amounts cannot have more than 4 decimal places, but the balances of a seeded account
+            // are not limited
+            engine
+                .seed_account(AccountStatus {
+                    held: dec!(0.00003),
+                    total: dec!(0.00003),
+                    ..AccountStatus::new(1)
+                })
+                .unwrap();
+            engine
+                .process_transaction(Transaction::new(
+                    1,
+                    1,
+                    TransactionType::Deposit {
+                        amount: amount(dec!(10.0)),
+                    },
+                ))
+                .unwrap();
+            engine
+                .process_transaction(Transaction::new(1, 1, TransactionType::Dispute))
+                .unwrap();
+
+            engine
+                .process_transaction(Transaction::new(1, 1, TransactionType::Chargeback))
+                .unwrap();
+
+            let account = engine.get_account(1, None).unwrap();
+            if clamp_chargeback_residue {
+                assert!(account.held.is_zero());
+                assert!(account.total.is_zero());
+            } else {
+                assert_eq!(account.held, dec!(0.00003));
+                assert_eq!(account.total, dec!(0.00003));
+            }
+            assert!(account.available.is_zero());
+        }
+    }
+
+    #[test]
+    fn test_no_lock_on_chargeback() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            no_lock_on_chargeback: true,
+            ..Default::default()
+        });
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(100.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(30.0)),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 1, TransactionType::Chargeback),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(30.0));
+        assert_eq!(account.held, dec!(0.0));
+        assert_eq!(account.total, dec!(30.0));
+        assert!(!account.locked);
+        assert_eq!(account.locked_by, None);
+
+        // The account keeps accepting transactions.
+        let withdrawal = Transaction::new(
+            1,
+            3,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(10.0)),
+            },
+        );
+        engine.process_transaction(withdrawal).unwrap();
+        assert_eq!(engine.get_account(1, None).unwrap().total, dec!(20.0));
+    }
+
+    #[test]
+    fn test_process_csv_line() {
+        let mut engine = PaymentEngine::new();
+        engine.process_csv_line("deposit,1,1,100.0").unwrap();
+        engine.process_csv_line("withdrawal,1,2,40.0").unwrap();
+        engine.process_csv_line("dispute,1,2").unwrap();
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(100.0));
+        assert_eq!(account.held, dec!(-40.0));
+        assert_eq!(account.total, dec!(60.0));
+
+        assert!(matches!(
+            engine.process_csv_line("deposit,1,3"),
+            Err(CsvTransactionError::MissingAmount("deposit"))
+        ));
+        assert!(matches!(
+            engine.process_csv_line("transfer,1,3,1.0"),
+            Err(CsvTransactionError::Csv(_))
+        ));
+        assert!(matches!(
+            engine.process_csv_line("withdrawal,1,3,1000.0"),
+            Err(CsvTransactionError::Engine(
+                PaymentEngineError::InsufficientFunds
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_auto_resolve_over() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            auto_resolve_over: Some(dec!(100.0)),
+            ..Default::default()
+        });
+        let hook = RecordingHook::default();
+        engine.set_hook(hook.clone());
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(30.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(10.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Deposit {
+                    amount: amount(dec!(40.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                4,
+                TransactionType::Deposit {
+                    amount: amount(dec!(90.0)),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+            Transaction::new(1, 2, TransactionType::Dispute),
+            Transaction::new(1, 3, TransactionType::Dispute),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+        assert_eq!(engine.get_account(1, None).unwrap().held, dec!(60.0));
+
+        // held would be 150, resolving the oldest deposit dispute brings it to 120 and the next
+        // one to 80, while the disputed withdrawal is kept
+        engine
+            .process_transaction(Transaction::new(1, 4, TransactionType::Dispute))
+            .unwrap();
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.held, dec!(80.0));
+        assert_eq!(account.available, dec!(70.0));
+        assert_eq!(account.total, dec!(150.0));
+        for (transaction_id, lifecycle) in [
+            (1, &[DisputeEvent::Disputed, DisputeEvent::Resolved][..]),
+            (2, &[DisputeEvent::Disputed][..]),
+            (3, &[DisputeEvent::Disputed, DisputeEvent::Resolved][..]),
+            (4, &[DisputeEvent::Disputed][..]),
+        ] {
+            assert_eq!(
+                engine.transaction_lifecycle(transaction_id).unwrap(),
+                lifecycle
+            );
+        }
+
+        // auto-resolved disputes are no longer open
+        assert!(matches!(
+            engine.process_transaction(Transaction::new(1, 1, TransactionType::Resolve)),
+            Err(PaymentEngineError::NotDisputed(1))
+        ));
+        let calls = hook.calls.lock().unwrap();
+        assert_eq!(
+            calls[calls.len() - 3..],
+            [("dispute", 4), ("resolve", 1), ("resolve", 3)]
+        );
+    }
+
+    #[test]
+    fn test_auto_resolve_over_guarded_release() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            auto_resolve_over: Some(dec!(0.0)),
+            guard_releases: true,
+            ..Default::default()
+        });
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(50.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(100.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(40.0)),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+
+        // held drops to 10, so releasing the 50 of the first dispute would make it negative; that
+        // dispute is kept, while the triggering one still succeeds
+        engine
+            .process_transaction(Transaction::new(1, 3, TransactionType::Dispute))
+            .unwrap();
+
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.held, dec!(10.0));
+        assert_eq!(account.available, dec!(100.0));
+        assert_eq!(account.total, dec!(110.0));
+        for transaction_id in [1, 3] {
+            assert_eq!(
+                engine.transaction_lifecycle(transaction_id).unwrap(),
+                [DisputeEvent::Disputed]
+            );
+        }
+    }
+
+    #[test]
+    fn test_disabled_types() {
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            disabled_types: HashSet::from(["dispute", "resolve", "chargeback"]),
+            ..Default::default()
+        });
+        engine
+            .process_transaction(Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            ))
+            .unwrap();
+
+        let result = engine.process_transaction(Transaction::new(1, 1, TransactionType::Dispute));
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::OperationDisabled("dispute"))
+        ));
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(10.0));
+        assert_eq!(account.held, dec!(0));
+        assert!(!engine.get_transaction(1).unwrap().is_disputed);
+
+        engine
+            .process_transaction(Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(4.0)),
+                },
+            ))
+            .unwrap();
+        assert_eq!(engine.get_account(1, None).unwrap().total, dec!(6.0));
+    }
+
+    #[test]
+    fn test_dispute_targets() {
+        let mut engine = PaymentEngine::new();
+        for transaction in [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(4.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                3,
+                TransactionType::PendingDeposit {
+                    amount: amount(dec!(1.0)),
+                },
+            ),
+        ] {
+            engine.process_transaction(transaction).unwrap();
+        }
+
+        engine
+            .process_transaction(Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::new(1, 2, TransactionType::Dispute))
+            .unwrap();
+        // failed attempts are counted as well
+        engine
+            .process_transaction(Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap_err();
+        engine
+            .process_transaction(Transaction::new(1, 3, TransactionType::Dispute))
+            .unwrap_err();
+        engine
+            .process_transaction(Transaction::new(1, 9, TransactionType::Dispute))
+            .unwrap_err();
+        engine
+            .process_transaction(Transaction::new(2, 10, TransactionType::Dispute))
+            .unwrap_err();
+        // only disputes are classified
+        engine
+            .process_transaction(Transaction::new(1, 1, TransactionType::Resolve))
+            .unwrap();
+
+        assert_eq!(
+            engine.dispute_targets(),
+            DisputeTargets {
+                deposits: 3,
+                withdrawals: 1,
+                missing: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_ignore_redundant_disputes() {
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            ),
+            Transaction::new(1, 1, TransactionType::Dispute),
+        ];
+
+        let (mut engine, _) = PaymentEngine::from_records(transactions.clone());
+        let result = engine.process_transaction(Transaction::new(1, 1, TransactionType::Dispute));
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::TransactionAlreadyDisputed(1))
+        ));
+
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            ignore_redundant_disputes: true,
+            ..Default::default()
+        });
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+        engine
+            .process_transaction(Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(0.0));
+        assert_eq!(account.held, dec!(10.0));
+        assert_eq!(
+            engine.transaction_lifecycle(1),
+            Some(vec![DisputeEvent::Disputed])
+        );
+
+        // the dispute is still resolved by a single resolve
+        engine
+            .process_transaction(Transaction::new(1, 1, TransactionType::Resolve))
+            .unwrap();
+        assert_eq!(engine.get_account(1, None).unwrap().available, dec!(10.0));
+    }
+
+    #[test]
+    fn test_enforce_held_le_total() {
+        let transactions = [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(100.0)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Withdrawal {
+                    amount: amount(dec!(100.0)),
+                },
+            ),
+        ];
+
+        // held exceeds total by default
+        let (mut engine, _) = PaymentEngine::from_records(transactions.clone());
+        engine
+            .process_transaction(Transaction::new(1, 1, TransactionType::Dispute))
+            .unwrap();
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.held, dec!(100.0));
+        assert_eq!(account.total, dec!(0.0));
+
+        let mut engine = PaymentEngine::with_config(PaymentEngineConfig {
+            enforce_held_le_total: true,
+            ..Default::default()
+        });
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+        let result = engine.process_transaction(Transaction::new(1, 1, TransactionType::Dispute));
+        assert!(matches!(
+            result,
+            Err(PaymentEngineError::HeldExceedsTotal(1))
+        ));
+        let account = engine.get_account(1, None).unwrap();
+        assert_eq!(account.available, dec!(0.0));
+        assert_eq!(account.held, dec!(0));
+        assert!(!engine.get_transaction(1).unwrap().is_disputed);
+
+        // disputes within the total are accepted
+        engine
+            .process_transaction(Transaction::new(
+                1,
+                3,
+                TransactionType::Deposit {
+                    amount: amount(dec!(10.0)),
+                },
+            ))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::new(1, 3, TransactionType::Dispute))
+            .unwrap();
+        assert_eq!(engine.get_account(1, None).unwrap().held, dec!(10.0));
+    }
 }
@@ -1,20 +1,118 @@
-use rust_decimal::Decimal;
+use serde::Serialize;
 
-#[derive(Debug, Clone)]
+use crate::payment_engine::Amount;
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Transaction {
     pub client: u16,
     pub id: u32,
+    /// Currency of the account the transaction applies to. `None` denotes the default account.
+    pub currency: Option<String>,
+    /// External reference of the transaction, see `dispute_by_ref`.
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
     pub(super) is_disputed: bool,
+    pub(super) is_pending: bool,
+    /// Dispute operations applied to the transaction, in the order of processing.
+    pub(super) lifecycle: Vec<DisputeEvent>,
+    #[serde(rename = "type")]
     pub r#type: TransactionType,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeEvent {
+    Disputed,
+    Resolved,
+    ChargedBack,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TransactionType {
-    Deposit { amount: Decimal },
-    Withdrawal { amount: Decimal },
+    Deposit {
+        amount: Amount,
+    },
+    Withdrawal {
+        amount: Amount,
+    },
     Dispute,
     Resolve,
     Chargeback,
+    /// Deposit whose funds are held until the transaction is settled.
+    PendingDeposit {
+        amount: Amount,
+    },
+    Settle,
+    /// Opens the account of the client, see `require_explicit_open`.
+    OpenAccount,
+    /// Freezes withdrawals from the account of the client, deposits are still accepted.
+    FreezeWithdrawals,
+    /// Lifts a freeze of the withdrawals set by `FreezeWithdrawals`.
+    UnfreezeWithdrawals,
+    /// Fee which is known, but not charged yet. It does not change the balances of the account.
+    PendingFee {
+        amount: Amount,
+    },
+}
+
+impl Transaction {
+    /// Whether `self` is an identical re-send of the `other` transaction.
+    pub fn is_retry_of(&self, other: &Transaction) -> bool {
+        self.id == other.id
+            && self.client == other.client
+            && self.currency == other.currency
+            && self.r#type == other.r#type
+    }
+
+    /// Whether `self` is a pending deposit which was not settled yet.
+    pub fn is_pending(&self) -> bool {
+        self.is_pending
+    }
+}
+
+impl TransactionType {
+    /// Names of all the transaction types, as returned by [`TransactionType::name`].
+    pub const NAMES: [&'static str; 11] = [
+        "deposit",
+        "withdrawal",
+        "dispute",
+        "resolve",
+        "chargeback",
+        "pending",
+        "settle",
+        "open",
+        "freeze",
+        "unfreeze",
+        "pending_fee",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionType::Deposit { .. } => "deposit",
+            TransactionType::Withdrawal { .. } => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::PendingDeposit { .. } => "pending",
+            TransactionType::Settle => "settle",
+            TransactionType::OpenAccount => "open",
+            TransactionType::FreezeWithdrawals => "freeze",
+            TransactionType::UnfreezeWithdrawals => "unfreeze",
+            TransactionType::PendingFee { .. } => "pending_fee",
+        }
+    }
+
+    /// Amount of the deposits, withdrawals and fees. Dispute operations carry no amount.
+    pub fn amount(&self) -> Option<Amount> {
+        match self {
+            TransactionType::Deposit { amount }
+            | TransactionType::Withdrawal { amount }
+            | TransactionType::PendingDeposit { amount }
+            | TransactionType::PendingFee { amount } => Some(*amount),
+            _ => None,
+        }
+    }
 }
 
 impl Transaction {
@@ -22,8 +120,35 @@ impl Transaction {
         Self {
             client,
             id: transaction_id,
+            currency: None,
+            reference: None,
             is_disputed: false,
+            is_pending: false,
+            lifecycle: Vec::new(),
             r#type,
         }
     }
+
+    pub fn with_currency(mut self, currency: Option<String>) -> Self {
+        self.currency = currency;
+        self
+    }
+
+    pub fn with_reference(mut self, reference: Option<String>) -> Self {
+        self.reference = reference;
+        self
+    }
+
+    /// Replaces the amount of a transaction which has one, e.g. with the amount of a withdrawal
+    /// actually withdrawn.
+    pub(super) fn with_amount(mut self, new_amount: Amount) -> Self {
+        match &mut self.r#type {
+            TransactionType::Deposit { amount }
+            | TransactionType::Withdrawal { amount }
+            | TransactionType::PendingDeposit { amount }
+            | TransactionType::PendingFee { amount } => *amount = new_amount,
+            _ => {}
+        }
+        self
+    }
 }
@@ -0,0 +1,345 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use clap::ValueEnum;
+use memmap2::MmapMut;
+use rust_decimal::Decimal;
+
+use crate::payment_engine::{Amount, DisputeEvent, Transaction, TransactionType};
+
+/// Storage of the processed transactions used by the [`PaymentEngine`](super::PaymentEngine) to
+/// look up the transactions referenced by dispute operations and settles.
+///
+/// The stores are not required to keep the transactions in memory, so they are returned by value
+/// (or borrowed, if possible) and modifications are written back with [`TransactionStore::insert`].
+pub trait TransactionStore: Debug + Send {
+    fn get(&self, transaction_id: u32) -> Option<Cow<'_, Transaction>>;
+    /// Stores the `transaction`, replacing the one with the same id, if any.
+    fn insert(&mut self, transaction: Transaction) -> io::Result<()>;
+    fn remove(&mut self, transaction_id: u32) -> Option<Transaction>;
+    fn contains(&self, transaction_id: u32) -> bool {
+        self.get(transaction_id).is_some()
+    }
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// All the stored transactions, in no particular order.
+    fn transactions(&self) -> Box<dyn Iterator<Item = Cow<'_, Transaction>> + '_>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TransactionStoreKind {
+    /// Transactions are kept in a hash map.
+    #[default]
+    Memory,
+    /// Transactions are kept in a memory-mapped temporary file indexed by the transaction id.
+    /// Suitable for inputs with more transactions than fit into memory.
+    Mmap,
+}
+
+impl TransactionStoreKind {
+    pub(super) fn build(self) -> io::Result<Box<dyn TransactionStore>> {
+        Ok(match self {
+            TransactionStoreKind::Memory => Box::new(HashMapTransactionStore::default()),
+            TransactionStoreKind::Mmap => Box::new(MmapTransactionStore::new()?),
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HashMapTransactionStore {
+    transactions: HashMap<u32, Transaction>,
+}
+
+impl TransactionStore for HashMapTransactionStore {
+    fn get(&self, transaction_id: u32) -> Option<Cow<'_, Transaction>> {
+        self.transactions.get(&transaction_id).map(Cow::Borrowed)
+    }
+
+    fn insert(&mut self, transaction: Transaction) -> io::Result<()> {
+        self.transactions.insert(transaction.id, transaction);
+        Ok(())
+    }
+
+    fn remove(&mut self, transaction_id: u32) -> Option<Transaction> {
+        self.transactions.remove(&transaction_id)
+    }
+
+    fn contains(&self, transaction_id: u32) -> bool {
+        self.transactions.contains_key(&transaction_id)
+    }
+
+    fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    fn transactions(&self) -> Box<dyn Iterator<Item = Cow<'_, Transaction>> + '_> {
+        Box::new(self.transactions.values().map(Cow::Borrowed))
+    }
+}
+
+/// Size of a single record of the [`MmapTransactionStore`]: flags, type, client and amount.
+const RECORD_SIZE: usize = 1 + 1 + 2 + 16;
+/// Number of records the file of the [`MmapTransactionStore`] initially has room for.
+const INITIAL_CAPACITY: u64 = 1024;
+
+const FLAG_PRESENT: u8 = 1;
+const FLAG_DISPUTED: u8 = 1 << 1;
+const FLAG_PENDING: u8 = 1 << 2;
+
+/// Parts of a transaction which do not fit into a fixed-size record. Most transactions have none,
+/// so they are kept in memory.
+#[derive(Debug, Default)]
+struct Extras {
+    currency: Option<String>,
+    reference: Option<String>,
+    lifecycle: Vec<DisputeEvent>,
+}
+
+/// Keeps the transactions as fixed-size records in a temporary file, the record of a transaction
+/// being at the offset given by its id. The file grows with the highest transaction id, but as it
+/// is sparse, only the pages with any records take up disk space. The file is removed once the
+/// store is dropped.
+#[derive(Debug)]
+pub struct MmapTransactionStore {
+    path: PathBuf,
+    file: File,
+    /// Always `Some`, except while being dropped.
+    map: Option<MmapMut>,
+    /// Number of the records the file has room for.
+    capacity: u64,
+    len: usize,
+    extras: HashMap<u32, Extras>,
+}
+
+impl MmapTransactionStore {
+    pub fn new() -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "transactions-processor-{}-{}.idx",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+
+        let mut store = Self {
+            path,
+            file,
+            map: None,
+            capacity: 0,
+            len: 0,
+            extras: HashMap::new(),
+        };
+        store.grow(INITIAL_CAPACITY)?;
+
+        Ok(store)
+    }
+
+    /// Extends the file to have room for `capacity` records and maps it anew.
+    fn grow(&mut self, capacity: u64) -> io::Result<()> {
+        self.map = None;
+        self.file.set_len(capacity * RECORD_SIZE as u64)?;
+        // SAFETY: the file is created exclusively for this store and is neither accessed nor
+        // resized by anything else while it is mapped.
+        self.map = Some(unsafe { MmapMut::map_mut(&self.file)? });
+        self.capacity = capacity;
+
+        Ok(())
+    }
+
+    fn map(&self) -> &MmapMut {
+        self.map.as_ref().expect("the file is mapped")
+    }
+
+    fn record(&self, transaction_id: u32) -> Option<&[u8]> {
+        if u64::from(transaction_id) >= self.capacity {
+            return None;
+        }
+
+        let offset = transaction_id as usize * RECORD_SIZE;
+        let record = &self.map()[offset..offset + RECORD_SIZE];
+        (record[0] & FLAG_PRESENT != 0).then_some(record)
+    }
+
+    fn decode(&self, transaction_id: u32, record: &[u8]) -> Transaction {
+        let amount = || {
+            let bytes = record[4..20].try_into().expect("the amount has 16 bytes");
+            Amount::new_unchecked(Decimal::deserialize(bytes))
+        };
+        let r#type = match record[1] {
+            0 => TransactionType::Deposit { amount: amount() },
+            1 => TransactionType::Withdrawal { amount: amount() },
+            2 => TransactionType::Dispute,
+            3 => TransactionType::Resolve,
+            4 => TransactionType::Chargeback,
+            5 => TransactionType::PendingDeposit { amount: amount() },
+            6 => TransactionType::Settle,
+            7 => TransactionType::OpenAccount,
+            8 => TransactionType::FreezeWithdrawals,
+            9 => TransactionType::UnfreezeWithdrawals,
+            10 => TransactionType::PendingFee { amount: amount() },
+            tag => unreachable!("invalid transaction type {tag}"),
+        };
+        let client = u16::from_le_bytes([record[2], record[3]]);
+
+        let mut transaction = Transaction::new(client, transaction_id, r#type);
+        transaction.is_disputed = record[0] & FLAG_DISPUTED != 0;
+        transaction.is_pending = record[0] & FLAG_PENDING != 0;
+        if let Some(extras) = self.extras.get(&transaction_id) {
+            transaction.currency = extras.currency.clone();
+            transaction.reference = extras.reference.clone();
+            transaction.lifecycle = extras.lifecycle.clone();
+        }
+
+        transaction
+    }
+}
+
+impl TransactionStore for MmapTransactionStore {
+    fn get(&self, transaction_id: u32) -> Option<Cow<'_, Transaction>> {
+        let record = self.record(transaction_id)?;
+        Some(Cow::Owned(self.decode(transaction_id, record)))
+    }
+
+    fn insert(&mut self, transaction: Transaction) -> io::Result<()> {
+        let transaction_id = transaction.id;
+        if u64::from(transaction_id) >= self.capacity {
+            let capacity = (self.capacity * 2).max(u64::from(transaction_id) + 1);
+            self.grow(capacity.min(u64::from(u32::MAX) + 1))?;
+        }
+        if !self.contains(transaction_id) {
+            self.len += 1;
+        }
+
+        let (tag, amount) = match transaction.r#type {
+            TransactionType::Deposit { amount } => (0, Some(amount)),
+            TransactionType::Withdrawal { amount } => (1, Some(amount)),
+            TransactionType::Dispute => (2, None),
+            TransactionType::Resolve => (3, None),
+            TransactionType::Chargeback => (4, None),
+            TransactionType::PendingDeposit { amount } => (5, Some(amount)),
+            TransactionType::Settle => (6, None),
+            TransactionType::OpenAccount => (7, None),
+            TransactionType::FreezeWithdrawals => (8, None),
+            TransactionType::UnfreezeWithdrawals => (9, None),
+            TransactionType::PendingFee { amount } => (10, Some(amount)),
+        };
+        let mut flags = FLAG_PRESENT;
+        if transaction.is_disputed {
+            flags |= FLAG_DISPUTED;
+        }
+        if transaction.is_pending {
+            flags |= FLAG_PENDING;
+        }
+
+        let offset = transaction_id as usize * RECORD_SIZE;
+        let record =
+            &mut self.map.as_mut().expect("the file is mapped")[offset..offset + RECORD_SIZE];
+        record[0] = flags;
+        record[1] = tag;
+        record[2..4].copy_from_slice(&transaction.client.to_le_bytes());
+        record[4..20].copy_from_slice(&amount.map_or(Decimal::ZERO, Amount::value).serialize());
+
+        if transaction.currency.is_none()
+            && transaction.reference.is_none()
+            && transaction.lifecycle.is_empty()
+        {
+            self.extras.remove(&transaction_id);
+        } else {
+            self.extras.insert(
+                transaction_id,
+                Extras {
+                    currency: transaction.currency,
+                    reference: transaction.reference,
+                    lifecycle: transaction.lifecycle,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    fn remove(&mut self, transaction_id: u32) -> Option<Transaction> {
+        let transaction = self.get(transaction_id)?.into_owned();
+
+        let offset = transaction_id as usize * RECORD_SIZE;
+        self.map.as_mut().expect("the file is mapped")[offset] = 0;
+        self.extras.remove(&transaction_id);
+        self.len -= 1;
+
+        Some(transaction)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn transactions(&self) -> Box<dyn Iterator<Item = Cow<'_, Transaction>> + '_> {
+        Box::new(
+            (0..self.capacity)
+                .filter_map(|transaction_id| self.get(transaction_id as u32))
+                .take(self.len),
+        )
+    }
+}
+
+impl Drop for MmapTransactionStore {
+    fn drop(&mut self) {
+        self.map = None;
+        // the file is of no use to anyone else, failing to remove it only wastes the disk space
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use super::*;
+
+    #[test]
+    fn test_mmap_store_roundtrip() {
+        let mut store = MmapTransactionStore::new().unwrap();
+        let path = store.path.clone();
+        let amount = Amount::try_new(dec!(12.3456), Amount::MAX_SCALE).unwrap();
+
+        let mut deposit = Transaction::new(7, 3, TransactionType::Deposit { amount })
+            .with_currency(Some("EUR".to_string()));
+        deposit.is_disputed = true;
+        deposit.lifecycle.push(DisputeEvent::Disputed);
+        store.insert(deposit.clone()).unwrap();
+        // beyond the initial capacity
+        let withdrawal = Transaction::new(1, 5000, TransactionType::Withdrawal { amount });
+        store.insert(withdrawal).unwrap();
+
+        assert_eq!(store.len(), 2);
+        let stored = store.get(3).unwrap();
+        assert_eq!(stored.client, 7);
+        assert_eq!(stored.r#type, deposit.r#type);
+        assert_eq!(stored.currency.as_deref(), Some("EUR"));
+        assert!(stored.is_disputed);
+        assert_eq!(stored.lifecycle, [DisputeEvent::Disputed]);
+        assert!(store.get(4).is_none());
+        assert!(store.get(u32::MAX).is_none());
+        assert_eq!(store.transactions().count(), 2);
+
+        let removed = store.remove(3).unwrap();
+        assert_eq!(removed.id, 3);
+        assert!(!store.contains(3));
+        assert_eq!(store.len(), 1);
+
+        drop(store);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,79 @@
+use rust_decimal::Decimal;
+use tracing::error;
+
+use transactions_processor::payment_engine::AccountStatus;
+
+/// Flags the accounts whose balances have a scale exceeding `scale`, which indicates that the
+/// balances accumulated more precision than expected. Returns the number of such accounts.
+pub fn check_balance_precision(accounts: &[AccountStatus], scale: u32) -> usize {
+    let exceeds = |amount: Decimal| amount.normalize().scale() > scale;
+
+    let mut drifted = 0;
+    for account in accounts {
+        if exceeds(account.available) || exceeds(account.held) || exceeds(account.total) {
+            error!(
+                client = account.client,
+                available = %account.available,
+                held = %account.held,
+                total = %account.total,
+                scale,
+                "account balance exceeds the expected precision"
+            );
+            drifted += 1;
+        }
+    }
+
+    drifted
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::dec;
+
+    use super::*;
+    use crate::test_utils::amount;
+    use transactions_processor::payment_engine::{
+        Amount, PaymentEngine, Transaction, TransactionType,
+    };
+
+    #[test]
+    fn test_check_balance_precision() {
+        let mut engine = PaymentEngine::new();
+        for transaction in [
+            Transaction::new(
+                1,
+                1,
+                TransactionType::Deposit {
+                    amount: amount(dec!(1.5)),
+                },
+            ),
+            Transaction::new(
+                1,
+                2,
+                TransactionType::Deposit {
+                    amount: amount(dec!(0.1234)),
+                },
+            ),
+            Transaction::new(
+                2,
+                3,
+                TransactionType::Deposit {
+                    amount: amount(dec!(2.0)),
+                },
+            ),
+            Transaction::new(
+                2,
+                4,
+                TransactionType::Withdrawal {
+                    amount: Amount::try_new(dec!(0.00001), 5).unwrap(),
+                },
+            ),
+        ] {
+            engine.process_transaction(transaction).unwrap();
+        }
+        let accounts = engine.get_accounts_statuses();
+
+        assert_eq!(check_balance_precision(&accounts, 4), 1);
+        assert_eq!(check_balance_precision(&accounts, 5), 0);
+    }
+}
@@ -0,0 +1,94 @@
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+
+use transactions_processor::payment_engine::PaymentEngine;
+
+/// Reads headerless CSV transactions (e.g. `deposit,1,1,100.0`) from the `input` line by line and
+/// after each one writes the state of the affected account to the `output` as a JSON line, or the
+/// reason the transaction was rejected. Empty lines are skipped. Stops at the end of the input.
+pub fn run_repl<R: BufRead, W: Write>(
+    mut engine: PaymentEngine,
+    input: R,
+    mut output: W,
+) -> Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match engine.process_csv_line(line) {
+            Ok(transaction) => {
+                if let Some(account) =
+                    engine.get_account(transaction.client, transaction.currency.as_deref())
+                {
+                    serde_json::to_writer(&mut output, account)?;
+                    writeln!(output)?;
+                }
+            }
+            Err(err) => writeln!(output, "error: {err}")?,
+        }
+        output.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use transactions_processor::payment_engine::PaymentEngineConfig;
+
+    use super::*;
+    use crate::cli::{Cli, Command};
+
+    #[test]
+    fn test_repl() {
+        let input = "deposit,1,1,100.0\n\
+                     withdrawal,1,2,40.0\n\
+                     \n\
+                     withdrawal,1,3,500.0\n\
+                     deposit,2,4,5.0,EUR\n\
+                     dispute,1,2\n";
+        let mut output = Vec::new();
+
+        run_repl(PaymentEngine::new(), input.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"client\":1,\"available\":\"100.0\",\"held\":\"0\",\"total\":\"100.0\",\"locked\":false}\n\
+             {\"client\":1,\"available\":\"60.0\",\"held\":\"0\",\"total\":\"60.0\",\"locked\":false}\n\
+             error: insufficient funds for withdrawal\n\
+             {\"client\":2,\"currency\":\"EUR\",\"available\":\"5.0\",\"held\":\"0\",\"total\":\"5.0\",\"locked\":false}\n\
+             {\"client\":1,\"available\":\"100.0\",\"held\":\"-40.0\",\"total\":\"60.0\",\"locked\":false}\n"
+        );
+    }
+
+    #[test]
+    fn test_repl_config() {
+        let cli = Cli::parse_from([
+            "transactions-processor",
+            "--disable-types",
+            "dispute",
+            "repl",
+        ]);
+        assert!(matches!(cli.command, Some(Command::Repl)));
+        let engine = PaymentEngine::try_with_config(PaymentEngineConfig::from(&cli)).unwrap();
+        let mut output = Vec::new();
+
+        run_repl(
+            engine,
+            "deposit,1,1,1.0\ndispute,1,1\n".as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"client\":1,\"available\":\"1.0\",\"held\":\"0\",\"total\":\"1.0\",\"locked\":false}\n\
+             error: dispute operations are disabled\n"
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::num::NonZeroU64;
+use std::path::{Path, PathBuf};
+
+/// Writer splitting CSV output into files of at most `max_rows` rows each, named
+/// `<PREFIX>.part1.csv`, `<PREFIX>.part2.csv` and so on. The first line written is taken as the
+/// header and repeated at the start of every file.
+///
+/// Rows are told apart by their line breaks, so no field may contain one.
+pub struct RollingWriter {
+    prefix: PathBuf,
+    max_rows: u64,
+    header: Vec<u8>,
+    header_complete: bool,
+    file: Option<BufWriter<File>>,
+    parts: u64,
+    rows_in_file: u64,
+    at_row_start: bool,
+}
+
+impl RollingWriter {
+    pub fn new(prefix: &Path, max_rows: NonZeroU64) -> Self {
+        Self {
+            prefix: prefix.to_path_buf(),
+            max_rows: max_rows.get(),
+            header: Vec::new(),
+            header_complete: false,
+            file: None,
+            parts: 0,
+            rows_in_file: 0,
+            at_row_start: true,
+        }
+    }
+
+    /// Path of the file with the given 1-based `part` number.
+    pub fn part_path(prefix: &Path, part: u64) -> PathBuf {
+        let mut path = OsString::from(prefix);
+        path.push(format!(".part{part}.csv"));
+        PathBuf::from(path)
+    }
+
+    /// Flushes the last file and returns the number of files written. If no rows were written, a
+    /// single file with just the header is created.
+    pub fn finish(mut self) -> io::Result<u64> {
+        if self.file.is_none() {
+            self.start_part()?;
+        }
+        if let Some(file) = &mut self.file {
+            file.flush()?;
+        }
+
+        Ok(self.parts)
+    }
+
+    fn start_part(&mut self) -> io::Result<()> {
+        if let Some(file) = &mut self.file {
+            file.flush()?;
+        }
+
+        self.parts += 1;
+        let mut file = BufWriter::new(File::create(Self::part_path(&self.prefix, self.parts))?);
+        file.write_all(&self.header)?;
+        self.file = Some(file);
+        self.rows_in_file = 0;
+
+        Ok(())
+    }
+}
+
+impl Write for RollingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let line_end = rest
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map(|index| index + 1);
+            let (chunk, remainder) = rest.split_at(line_end.unwrap_or(rest.len()));
+            rest = remainder;
+
+            if !self.header_complete {
+                self.header.extend_from_slice(chunk);
+                self.header_complete = line_end.is_some();
+                continue;
+            }
+
+            if self.at_row_start && (self.file.is_none() || self.rows_in_file == self.max_rows) {
+                self.start_part()?;
+            }
+            self.file
+                .as_mut()
+                .expect("a part is started before any row")
+                .write_all(chunk)?;
+            self.at_row_start = line_end.is_some();
+            if self.at_row_start {
+                self.rows_in_file += 1;
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
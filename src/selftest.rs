@@ -0,0 +1,123 @@
+use anyhow::{Result, bail};
+use rust_decimal::dec;
+
+use crate::output::{OutputOptions, write_accounts};
+use transactions_processor::payment_engine::{Amount, PaymentEngine, Transaction, TransactionType};
+
+/// Number of transactions of the canonical sequence which are expected to be rejected.
+const EXPECTED_FAILED: usize = 2;
+
+/// Accounts expected after processing the canonical sequence.
+const EXPECTED_OUTPUT: &str = "\
+client,available,held,total,locked
+1,40.5,0.0,40.5,false
+2,0.0000,0.0000,0.0000,true
+3,0.0,10.0,10.0,false
+";
+
+/// Transaction sequence covering every transaction type, including the rejected operations.
+fn canonical_transactions() -> Result<Vec<Transaction>> {
+    let amount = |value| Amount::try_new(value, Amount::MAX_SCALE);
+
+    Ok(vec![
+        Transaction::new(
+            1,
+            1,
+            TransactionType::Deposit {
+                amount: amount(dec!(100.0))?,
+            },
+        ),
+        Transaction::new(
+            1,
+            2,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(59.5))?,
+            },
+        ),
+        // Insufficient funds
+        Transaction::new(
+            1,
+            3,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(50.0))?,
+            },
+        ),
+        Transaction::new(
+            2,
+            4,
+            TransactionType::Deposit {
+                amount: amount(dec!(25.1234))?,
+            },
+        ),
+        Transaction::new(2, 4, TransactionType::Dispute),
+        Transaction::new(2, 4, TransactionType::Chargeback),
+        // Account locked
+        Transaction::new(
+            2,
+            5,
+            TransactionType::Deposit {
+                amount: amount(dec!(1.0))?,
+            },
+        ),
+        Transaction::new(1, 2, TransactionType::Dispute),
+        Transaction::new(1, 2, TransactionType::Resolve),
+        Transaction::new(
+            3,
+            6,
+            TransactionType::PendingDeposit {
+                amount: amount(dec!(10.0))?,
+            },
+        ),
+        Transaction::new(
+            3,
+            7,
+            TransactionType::PendingDeposit {
+                amount: amount(dec!(5.0))?,
+            },
+        ),
+        Transaction::new(3, 7, TransactionType::Settle),
+        Transaction::new(
+            3,
+            8,
+            TransactionType::Withdrawal {
+                amount: amount(dec!(5.0))?,
+            },
+        ),
+    ])
+}
+
+/// Processes the canonical transaction sequence with a fresh engine and compares the resulting
+/// accounts with the known-good output.
+pub fn run_selftest() -> Result<()> {
+    let mut engine = PaymentEngine::new();
+    let mut failed = 0;
+    for transaction in canonical_transactions()? {
+        if engine.process_transaction(transaction).is_err() {
+            failed += 1;
+        }
+    }
+    if failed != EXPECTED_FAILED {
+        bail!("{failed} transactions failed, expected {EXPECTED_FAILED}");
+    }
+
+    let mut accounts = engine.get_accounts_statuses();
+    accounts.sort_by_key(|a| a.client);
+    let mut output = Vec::new();
+    write_accounts(&mut output, &accounts, &OutputOptions::default())?;
+    let output = String::from_utf8(output)?;
+    if output != EXPECTED_OUTPUT {
+        bail!("unexpected accounts:\n{output}expected:\n{EXPECTED_OUTPUT}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selftest_passes() {
+        run_selftest().unwrap();
+    }
+}
@@ -0,0 +1,156 @@
+use std::fs::{self, File};
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::Utc;
+
+use crate::output::{OutputOptions, write_accounts};
+use transactions_processor::payment_engine::PaymentEngine;
+
+const PREFIX: &str = "accounts-";
+const EXTENSION: &str = ".csv";
+
+/// Writes the accounts CSV of all the accounts to a new file in a directory every `interval`
+/// processed transactions, so that the state can be observed while the input is still being
+/// processed. The files are named `accounts-<TIMESTAMP>-<PROCESSED>.csv` and only the `keep` most
+/// recent ones are kept in the directory.
+///
+/// Every snapshot is written to a temporary file first and then renamed, so a reader never sees a
+/// partially written snapshot.
+pub struct SnapshotWriter {
+    dir: PathBuf,
+    interval: u64,
+    keep: usize,
+    options: OutputOptions,
+    processed: u64,
+}
+
+impl SnapshotWriter {
+    pub fn new(
+        dir: &Path,
+        interval: NonZeroU64,
+        keep: NonZeroUsize,
+        options: OutputOptions,
+    ) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            interval: interval.get(),
+            keep: keep.get(),
+            options,
+            processed: 0,
+        })
+    }
+
+    /// Counts a processed transaction and writes a snapshot of the accounts of the
+    /// `payment_engine` if another `interval` transactions were processed.
+    pub fn transaction_processed(&mut self, payment_engine: &PaymentEngine) -> Result<()> {
+        self.processed += 1;
+        if !self.processed.is_multiple_of(self.interval) {
+            return Ok(());
+        }
+
+        // the processed count tells apart the snapshots written within the same microsecond
+        let name = format!(
+            "{PREFIX}{}-{:020}{EXTENSION}",
+            Utc::now().format("%Y%m%dT%H%M%S%.6fZ"),
+            self.processed
+        );
+        let temporary_path = self.dir.join(format!(".{name}.tmp"));
+        write_accounts(
+            File::create(&temporary_path)?,
+            &payment_engine.get_accounts_statuses(),
+            &self.options,
+        )?;
+        fs::rename(&temporary_path, self.dir.join(name))?;
+
+        self.remove_old_snapshots()
+    }
+
+    /// Paths of the snapshots in the directory, the oldest first.
+    pub fn snapshots(dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut snapshots = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_snapshot = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(PREFIX) && name.ends_with(EXTENSION));
+            if is_snapshot {
+                snapshots.push(path);
+            }
+        }
+        // the timestamps sort lexicographically
+        snapshots.sort();
+
+        Ok(snapshots)
+    }
+
+    fn remove_old_snapshots(&self) -> Result<()> {
+        let snapshots = Self::snapshots(&self.dir)?;
+        let excess = snapshots.len().saturating_sub(self.keep);
+        for snapshot in &snapshots[..excess] {
+            fs::remove_file(snapshot)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use rust_decimal::dec;
+    use transactions_processor::payment_engine::{Transaction, TransactionType};
+
+    use super::*;
+    use crate::test_utils::amount;
+
+    fn deposit(client: u16, tx: u32) -> Transaction {
+        Transaction::new(
+            client,
+            tx,
+            TransactionType::Deposit {
+                amount: amount(dec!(1.0)),
+            },
+        )
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = env::temp_dir().join(format!(
+            "transactions-processor-{}-snapshot-rotation",
+            process::id()
+        ));
+        let mut snapshots = SnapshotWriter::new(
+            &dir,
+            NonZeroU64::new(1).unwrap(),
+            NonZeroUsize::new(2).unwrap(),
+            OutputOptions::default(),
+        )
+        .unwrap();
+        let mut engine = PaymentEngine::new();
+        for tx in 1..=3 {
+            engine.process_transaction(deposit(1, tx)).unwrap();
+            snapshots.transaction_processed(&engine).unwrap();
+        }
+
+        let paths = SnapshotWriter::snapshots(&dir).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(
+            fs::read_to_string(&paths[0]).unwrap(),
+            "client,available,held,total,locked\n1,2.0,0,2.0,false\n"
+        );
+        assert_eq!(
+            fs::read_to_string(&paths[1]).unwrap(),
+            "client,available,held,total,locked\n1,3.0,0,3.0,false\n"
+        );
+        // no temporary files are left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
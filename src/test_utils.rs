@@ -0,0 +1,8 @@
+use rust_decimal::Decimal;
+
+use transactions_processor::payment_engine::Amount;
+
+/// Amount of the given `value`, which must be valid.
+pub fn amount(value: Decimal) -> Amount {
+    Amount::try_new(value, Amount::MAX_SCALE).unwrap()
+}
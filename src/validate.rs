@@ -0,0 +1,145 @@
+use std::io::{Read, Write};
+
+use anyhow::Result;
+use csv::{ErrorKind, ReaderBuilder, StringRecord};
+
+use transactions_processor::payment_engine::{CsvTransaction, CsvTransactionError, Transaction};
+
+/// Scans the transactions CSV from the `input` without processing it and writes up to
+/// `max_problems` problems found in the records (e.g. an unparseable amount or an unknown type) to
+/// the `output`, one per line with the line number of the record. Stops reading once
+/// `max_problems` problems are found. Returns the number of the reported problems.
+pub fn validate_transactions<R: Read, W: Write>(
+    input: R,
+    max_problems: usize,
+    mut output: W,
+) -> Result<usize> {
+    let mut reader = ReaderBuilder::new().from_reader(input);
+    let headers = reader.headers()?.clone();
+    if let Err(err) = CsvTransaction::validate_headers(&headers) {
+        // the records cannot be checked without knowing the columns
+        writeln!(output, "line 1: {err}")?;
+        return Ok(1);
+    }
+
+    let mut problems = 0;
+    let mut record = StringRecord::new();
+    while problems < max_problems {
+        let problem = match reader.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) if record.iter().all(|field| field.trim().is_empty()) => continue,
+            Ok(true) => check_record(&record, &headers),
+            Err(err) => Some(err.to_string()),
+        };
+        if let Some(problem) = problem {
+            let line = record.position().map_or(0, |position| position.line());
+            writeln!(output, "line {line}: {problem}")?;
+            problems += 1;
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Describes the first problem of the `record`, if any.
+fn check_record(record: &StringRecord, headers: &StringRecord) -> Option<String> {
+    let err = match CsvTransaction::from_record(record, headers) {
+        Ok(transaction) => Transaction::try_from(transaction).err()?,
+        Err(err) => err,
+    };
+
+    Some(match err {
+        CsvTransactionError::Csv(err) => match err.kind() {
+            ErrorKind::Deserialize { err, .. } => {
+                match err.field().and_then(|field| headers.get(field as usize)) {
+                    Some(column) => format!("{column}: {}", err.kind()),
+                    None => err.kind().to_string(),
+                }
+            }
+            _ => err.to_string(),
+        },
+        // the line is reported separately
+        CsvTransactionError::ClientOutOfRange { client, .. } => {
+            format!("client {client} is out of range (at most 65535)")
+        }
+        err => err.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(input: &str, max_problems: usize) -> (usize, String) {
+        let mut output = Vec::new();
+        let problems = validate_transactions(input.as_bytes(), max_problems, &mut output).unwrap();
+        (problems, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_validate_transactions() {
+        let input = "type,client,tx,amount\n\
+                     deposit,1,1,10.0\n\
+                     deposit,1,2,1.0.0\n\
+                     \n\
+                     withdrawal,1,3,1.0\n\
+                     deposit,1,4,\n";
+
+        assert_eq!(
+            validate(input, 10),
+            (
+                2,
+                "line 3: Invalid decimal: two decimal points\n\
+                 line 6: amount is required for deposit\n"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            validate(input, 1),
+            (
+                1,
+                "line 3: Invalid decimal: two decimal points\n".to_string()
+            )
+        );
+        assert_eq!(
+            validate("type,client,tx,amount\ndeposit,1,1,1.0\n", 10).0,
+            0
+        );
+    }
+
+    #[test]
+    fn test_validate_transactions_problem_kinds() {
+        let input = "type,client,tx,amount\n\
+                     deposit,70000,1,1.0\n\
+                     transfer,1,2,1.0\n\
+                     withdrawal,1,3,-1.0\n";
+
+        let (problems, output) = validate(input, 10);
+
+        assert_eq!(problems, 3);
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "line 2: client 70000 is out of range (at most 65535)"
+        );
+        assert!(
+            lines[1].starts_with("line 3: unknown variant `transfer`"),
+            "{}",
+            lines[1]
+        );
+        assert!(
+            lines[2].starts_with("line 4: invalid transaction amount"),
+            "{}",
+            lines[2]
+        );
+
+        assert_eq!(
+            validate("type,client,amount\ndeposit,1,1.0\n", 10),
+            (
+                1,
+                "line 1: invalid header (missing columns: [tx], duplicated columns: [])\n"
+                    .to_string()
+            )
+        );
+    }
+}